@@ -1,3 +1,326 @@
+//! Validates the GLSL in `assets/` so that shader typos fail the build instead of panicking at runtime.
+//!
+//! Every `.vert` and `.frag` file is preprocessed the same way as at runtime and checked for:
+//! - a leading `#version 330` / `#version 330 core`;
+//! - balanced brackets and a `main` function;
+//! - names that do not exist in GLSL 330 core (`attribute`, `varying`, `gl_FragColor`, `texture2D`, ...);
+//! - fragment inputs without a matching vertex output. Vertex and fragment shaders are paired
+//!   when they share a file stem, or when an example names a `.vert` followed by a `.frag`.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+#[allow(dead_code)]
+#[path = "src/preprocess.rs"]
+mod preprocess;
+#[allow(dead_code)]
+#[path = "src/resource.rs"]
+mod resource;
+
+use preprocess::ShaderSource;
+use resource::AssetManager;
+
+const REMOVED_IN_CORE: &[(&str, &str)] = &[
+    ("attribute", "use `in`"),
+    ("varying", "use `out` in the vertex shader and `in` in the fragment shader"),
+    ("gl_FragColor", "declare an `out vec4`"),
+    ("gl_FragData", "declare `out` variables"),
+    ("texture2D", "use `texture`"),
+    ("texture2DProj", "use `textureProj`"),
+    ("textureCube", "use `texture`"),
+    ("ftransform", "multiply by the projection matrix yourself")
+];
+
+/// An `in` or `out` variable declared at global scope
+struct Interface {
+    name: String,
+    ty: String,
+    line: u32
+}
+
+struct Shader {
+    name: String,
+    source: ShaderSource,
+    inputs: Vec<Interface>,
+    outputs: Vec<Interface>
+}
+
+struct Diagnostics {
+    errors: Vec<String>
+}
+
+impl Diagnostics {
+
+    fn error(&mut self, source: &ShaderSource, line: u32, message: &str) {
+        let location = match source.location(line) {
+            Some((file, line)) => format!("assets/{}:{}", file, line),
+            None => "assets".to_string()
+        };
+        self.errors.push(format!("{}: {}", location, message));
+    }
+
+}
 
 fn main() {
-}
\ No newline at end of file
+    let root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let assets_dir = root.join("assets");
+    println!("cargo:rerun-if-changed={}", assets_dir.display());
+    println!("cargo:rerun-if-changed={}", root.join("examples").display());
+
+    let assets = AssetManager::with_roots(vec![assets_dir.clone()]);
+    let mut diagnostics = Diagnostics { errors: vec![] };
+    let mut shaders = vec![];
+
+    for name in stage_files(&assets_dir) {
+        let source = match assets.load_shader(&name, &[]) {
+            Ok(source) => source,
+            Err(e) => {
+                diagnostics.errors.push(format!("assets/{}: {}", name, e));
+                continue;
+            }
+        };
+        shaders.push(check(name, source, &mut diagnostics));
+    }
+
+    for (vs, fs) in pairs(&root, &shaders) {
+        let vertex = shaders.iter().find(|s| s.name == vs);
+        let fragment = shaders.iter().find(|s| s.name == fs);
+        if let (Some(vertex), Some(fragment)) = (vertex, fragment) {
+            check_interface(vertex, fragment, &mut diagnostics);
+        }
+    }
+
+    if !diagnostics.errors.is_empty() {
+        for e in &diagnostics.errors {
+            eprintln!("error: {}", e);
+        }
+        eprintln!("{} shader error(s) in assets/", diagnostics.errors.len());
+        process::exit(1);
+    }
+}
+
+/// `.vert` and `.frag` files directly in `dir`, sorted by name
+fn stage_files(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir).map(|entries| {
+        entries.filter_map(|e| e.ok())
+            .filter(|e| e.path().is_file())
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .filter(|n| n.ends_with(".vert") || n.ends_with(".frag"))
+            .collect()
+    }).unwrap_or_default();
+    names.sort();
+    names
+}
+
+fn check(name: String, source: ShaderSource, diagnostics: &mut Diagnostics) -> Shader {
+    let code = strip_comments(source.code());
+    let lines: Vec<&str> = code.lines().collect();
+
+    match lines.iter().position(|l| !l.trim().is_empty()) {
+        Some(i) => {
+            let words: Vec<&str> = lines[i].split_whitespace().collect();
+            if words.first() != Some(&"#version") {
+                diagnostics.error(&source, i as u32 + 1, "the first line must be `#version 330 core`");
+            } else if words[1..] != ["330"] && words[1..] != ["330", "core"] {
+                diagnostics.error(&source, i as u32 + 1, &format!(
+                    "expected `#version 330 core`, found `{}`", lines[i].trim()));
+            }
+        },
+        None => diagnostics.error(&source, 1, "empty shader")
+    }
+
+    // Brackets
+    let mut open: Vec<(char, u32)> = vec![];
+    for (i, l) in lines.iter().enumerate() {
+        let line = i as u32 + 1;
+        for c in l.chars() {
+            match c {
+                '(' | '[' | '{' => open.push((c, line)),
+                ')' | ']' | '}' => {
+                    let expected = match c { ')' => '(', ']' => '[', _ => '{' };
+                    match open.pop() {
+                        Some((o, _)) if o == expected => (),
+                        Some((o, at)) => {
+                            diagnostics.error(&source, line, &format!("`{}` does not match `{}` opened on line {}", c, o,
+                                source.location(at).map(|(_, l)| l).unwrap_or(at)));
+                            open.clear();
+                        },
+                        None => diagnostics.error(&source, line, &format!("unmatched `{}`", c))
+                    }
+                },
+                _ => ()
+            }
+        }
+    }
+    for &(c, line) in &open {
+        diagnostics.error(&source, line, &format!("`{}` is never closed", c));
+    }
+
+    // Removed names, and main
+    let mut has_main = false;
+    for (i, l) in lines.iter().enumerate() {
+        let words = identifiers(l);
+        for (j, w) in words.iter().enumerate() {
+            if let Some(&(_, hint)) = REMOVED_IN_CORE.iter().find(|&&(removed, _)| removed == *w) {
+                diagnostics.error(&source, i as u32 + 1, &format!("`{}` is not available in GLSL 330 core, {}", w, hint));
+            }
+            if *w == "main" && j > 0 && words[j - 1] == "void" {
+                has_main = true;
+            }
+        }
+    }
+    if !has_main {
+        diagnostics.error(&source, 1, "no `void main()`");
+    }
+
+    let (inputs, outputs) = interfaces(&lines);
+    Shader { name, source, inputs, outputs }
+}
+
+/// Replace comments with spaces, keeping line breaks so line numbers stay the same
+fn strip_comments(code: &str) -> String {
+    let mut out = String::with_capacity(code.len());
+    let mut chars = code.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek().cloned()) {
+            ('/', Some('/')) => {
+                while let Some(&n) = chars.peek() {
+                    if n == '\n' {
+                        break;
+                    }
+                    chars.next();
+                }
+            },
+            ('/', Some('*')) => {
+                chars.next();
+                let mut last = ' ';
+                while let Some(n) = chars.next() {
+                    if n == '\n' {
+                        out.push('\n');
+                    }
+                    if last == '*' && n == '/' {
+                        break;
+                    }
+                    last = n;
+                }
+                out.push(' ');
+            },
+            _ => out.push(c)
+        }
+    }
+    out
+}
+
+fn identifiers(line: &str) -> Vec<&str> {
+    line.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+/// Global `in` and `out` declarations, skipping interface blocks
+fn interfaces(lines: &[&str]) -> (Vec<Interface>, Vec<Interface>) {
+    let mut inputs = vec![];
+    let mut outputs = vec![];
+    let mut depth = 0;
+    let mut statement = String::new();
+    let mut start = 1;
+
+    for (i, l) in lines.iter().enumerate() {
+        if l.trim_start().starts_with('#') {
+            continue;
+        }
+        for c in l.chars() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => ()
+            }
+            if statement.trim().is_empty() {
+                start = i as u32 + 1;
+            }
+            if c == ';' && depth == 0 {
+                if !statement.contains('{') {
+                    let mut words: Vec<&str> = identifiers(&statement);
+                    // layout(location = 0) in vec3 aPos
+                    if words.first() == Some(&"layout") {
+                        let after = statement.find(')').map(|p| &statement[p + 1..]).unwrap_or("");
+                        words = identifiers(after);
+                    }
+                    let words: Vec<&str> = words.into_iter()
+                        .filter(|w| !["flat", "smooth", "noperspective", "centroid", "invariant"].contains(w))
+                        .collect();
+                    if words.len() >= 3 {
+                        let interface = Interface { name: words[2].to_string(), ty: words[1].to_string(), line: start };
+                        match words[0] {
+                            "in" => inputs.push(interface),
+                            "out" => outputs.push(interface),
+                            _ => ()
+                        }
+                    }
+                }
+                statement.clear();
+            } else {
+                statement.push(c);
+            }
+        }
+        statement.push('\n');
+    }
+    (inputs, outputs)
+}
+
+/// (vertex, fragment) pairs to check against each other
+fn pairs(root: &Path, shaders: &[Shader]) -> BTreeSet<(String, String)> {
+    let mut pairs = BTreeSet::new();
+    for s in shaders.iter().filter(|s| s.name.ends_with(".vert")) {
+        let stem = &s.name[..s.name.len() - ".vert".len()];
+        let fragment = format!("{}.frag", stem);
+        if shaders.iter().any(|f| f.name == fragment) {
+            pairs.insert((s.name.clone(), fragment));
+        }
+    }
+
+    // "xx.vert" followed by "yy.frag" in an example
+    if let Ok(entries) = fs::read_dir(root.join("examples")) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            let code = match fs::read_to_string(entry.path()) {
+                Ok(code) => code,
+                Err(_) => continue
+            };
+            let mut vertex: Option<String> = None;
+            for (i, literal) in code.split('"').enumerate() {
+                if i % 2 == 0 {
+                    continue;
+                }
+                if literal.ends_with(".vert") {
+                    vertex = Some(literal.to_string());
+                } else if literal.ends_with(".frag") {
+                    if let Some(vs) = vertex.take() {
+                        pairs.insert((vs, literal.to_string()));
+                    }
+                }
+            }
+        }
+    }
+    pairs
+}
+
+fn check_interface(vertex: &Shader, fragment: &Shader, diagnostics: &mut Diagnostics) {
+    for input in &fragment.inputs {
+        match vertex.outputs.iter().find(|o| o.name == input.name) {
+            Some(output) if output.ty != input.ty => diagnostics.error(&fragment.source, input.line, &format!(
+                "`in {} {}` does not match `out {} {}` in {}", input.ty, input.name, output.ty, output.name, vertex.name)),
+            Some(_) => (),
+            None => diagnostics.error(&fragment.source, input.line, &format!(
+                "`in {} {}` is not written by {}", input.ty, input.name, vertex.name))
+        }
+    }
+    for output in &vertex.outputs {
+        if !fragment.inputs.iter().any(|i| i.name == output.name) {
+            println!("cargo:warning=assets/{}: `out {} {}` is not read by {}",
+                     vertex.name, output.ty, output.name, fragment.name);
+        }
+    }
+}
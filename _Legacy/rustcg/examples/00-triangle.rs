@@ -1,9 +1,11 @@
 #[macro_use]
 extern crate gfx;
 extern crate rustcg;
+extern crate gfx_device_gl;
 
 use rustcg::*;
 use gfx::traits::FactoryExt;
+use gfx_device_gl::{CommandBuffer, Resources};
 
 // Define pipeline `pipe`
 gfx_defines! {
@@ -25,21 +27,28 @@ impl Vertex {
     }
 }
 
-fn main() {
-    let mut ctx = GameContext::init();
+struct Triangle {
+    encoder: gfx::Encoder<Resources, CommandBuffer>,
+    program: ShaderProgram<pipe::Init<'static>>,
+    vertex_buffer: gfx::handle::Buffer<Resources, Vertex>,
+    slice: gfx::Slice<Resources>
+}
 
-    let (mut encoder, pso, vertex_buffer, slice) = {
-        let mut gfx = &mut ctx.gfx;
+struct TriangleGame {
+    state: Option<Triangle>
+}
 
-        // Create Encoder (i.e. command buffer)
-        let mut encoder: gfx::Encoder<_, _> = gfx.factory.create_command_buffer().into();
+impl Game for TriangleGame {
+
+    fn init(&mut self, ctx: &mut GameContext) {
+        // Create pso, which is rebuilt whenever the shader files change
+        let program = ShaderProgram::new(ctx, "00-triangle.vert", "00-triangle.frag", pipe::new())
+            .unwrap_or_else(|e| panic!("{}", e));
 
-        // Create pso
-        let pso = gfx.factory.create_pipeline_simple(
-            rustcg::resource::load_bytes("00-triangle.vert").as_slice(),
-            rustcg::resource::load_bytes("00-triangle.frag").as_slice(),
-            pipe::new()
-        ).unwrap();
+        let gfx = &mut ctx.gfx;
+
+        // Create Encoder (i.e. command buffer)
+        let encoder: gfx::Encoder<_, _> = gfx.factory.create_command_buffer().into();
 
         // Vertex data
         let triangle: [Vertex; 3] = [
@@ -51,29 +60,37 @@ fn main() {
         // Create VBO
         let (vertex_buffer, slice) = gfx.factory.create_vertex_buffer_with_slice(&triangle, ());
 
-        (encoder, pso, vertex_buffer, slice)
-    };
-
-    let mut running = true;
-    while running {
-        ctx.process_events(&mut running);
-
-        {
-            let ref mut gfx = ctx.gfx;
-            // Emit draw calls
-            // !! Note that vertex_buffer and rtv are all HANDLES to underlying buffer,
-            //   and here we DUPLICATE the handle.
-            let pipe_data = pipe::Data {
-                vbuf: vertex_buffer.clone(),
-                out_color: gfx.color_view.clone(),
-            };
-            encoder.clear(&gfx.color_view, [0.2, 0.2, 0.3, 1.0]);
-            encoder.draw(&slice, &pso, &pipe_data);
-    
-            // Flush
-            encoder.flush(&mut gfx.device);
+        self.state = Some(Triangle { encoder, program, vertex_buffer, slice });
+    }
+
+    fn update(&mut self, ctx: &mut GameContext) {
+        if ctx.input().is_key_pressed(VirtualKeyCode::Escape) {
+            ctx.exit();
         }
 
-        ctx.frame_end();
+        let state = self.state.as_mut().unwrap();
+        state.program.update(ctx);
+
+        let ref mut gfx = ctx.gfx;
+        // Emit draw calls
+        // !! Note that vertex_buffer and rtv are all HANDLES to underlying buffer,
+        //   and here we DUPLICATE the handle.
+        let pipe_data = pipe::Data {
+            vbuf: state.vertex_buffer.clone(),
+            out_color: gfx.color_view.clone(),
+        };
+        state.encoder.clear(&gfx.color_view, [0.2, 0.2, 0.3, 1.0]);
+        state.encoder.draw(&state.slice, state.program.pso(), &pipe_data);
+
+        // Flush
+        state.encoder.flush(&mut gfx.device);
+    }
+
+}
+
+fn main() {
+    let config = ContextConfig::default().title("00 - triangle").vsync(true).screenshot_key(VirtualKeyCode::F12);
+    if let Err(e) = rustcg::run(config, TriangleGame { state: None }) {
+        eprintln!("{}", e);
     }
-}
\ No newline at end of file
+}
@@ -35,7 +35,7 @@ impl Vertex {
 }
 
 fn main() {
-    let mut ctx = GameContext::init();
+    let mut ctx = GameContext::init(ContextConfig::default()).unwrap();
 
     let (mut encoder, pso, vertex_buffer, slice) = {
         let mut gfx = &mut ctx.gfx;
@@ -45,8 +45,8 @@ fn main() {
 
         // Create pso
         let pso = gfx.factory.create_pipeline_simple(
-            rustcg::resource::load_bytes("01-projection.vert").as_slice(),
-            rustcg::resource::load_bytes("00-triangle.frag").as_slice(),
+            rustcg::resource::load_bytes("01-projection.vert").unwrap().as_slice(),
+            rustcg::resource::load_bytes("00-triangle.frag").unwrap().as_slice(),
             pipe::new()
         ).unwrap();
 
@@ -133,7 +133,7 @@ impl Ray {
 }
 
 fn main() {
-    let mut ctx = GameContext::init();
+    let mut ctx = GameContext::init(ContextConfig::default()).unwrap();
 
     let (mut encoder, pso, vbo, slice) = {
         let mut gfx = &mut ctx.gfx;
@@ -143,8 +143,8 @@ fn main() {
 
         // Create pso
         let pso = gfx.factory.create_pipeline_simple(
-            rustcg::resource::load_bytes("02-raytracer.vert").as_slice(),
-            rustcg::resource::load_bytes("02-raytracer.frag").as_slice(),
+            rustcg::resource::load_bytes("02-raytracer.vert").unwrap().as_slice(),
+            rustcg::resource::load_bytes("02-raytracer.frag").unwrap().as_slice(),
             pipe::new()
         ).unwrap();
 
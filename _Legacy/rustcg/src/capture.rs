@@ -0,0 +1,270 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An RGBA8 image, top row first.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>
+}
+
+impl Image {
+
+    /// Build an image from GL readback, which puts the bottom row first.
+    pub fn from_gl_rows(width: u32, height: u32, mut pixels: Vec<u8>) -> Image {
+        flip_rows(&mut pixels, width as usize * 4);
+        Image { width, height, pixels }
+    }
+
+    /// Write the image as PNG or PPM, depending on the extension of `path` (PNG if it has none).
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let ppm = path.extension().and_then(|e| e.to_str()).map(|e| e.eq_ignore_ascii_case("ppm")).unwrap_or(false);
+        let mut w = BufWriter::new(File::create(path)?);
+        if ppm {
+            self.write_ppm(&mut w)?;
+        } else {
+            self.write_png(&mut w)?;
+        }
+        w.flush()
+    }
+
+    /// Binary PPM (P6). PPM has no alpha channel, so alpha is dropped.
+    pub fn write_ppm<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+        let rgb: Vec<u8> = self.pixels.chunks(4).flat_map(|p| p[..3].iter().cloned()).collect();
+        w.write_all(&rgb)
+    }
+
+    /// 8-bit RGBA PNG. The image data is stored without compression, which keeps this free of dependencies.
+    pub fn write_png<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+        let mut header = vec![];
+        header.extend_from_slice(&be32(self.width));
+        header.extend_from_slice(&be32(self.height));
+        // 8 bits per channel, RGBA, deflate, adaptive filtering, no interlace
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+        write_chunk(w, b"IHDR", &header)?;
+
+        // Each row starts with filter type 0 (none)
+        let stride = self.width as usize * 4;
+        let mut raw = Vec::with_capacity((stride + 1) * self.height as usize);
+        for row in self.pixels.chunks(stride) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+        write_chunk(w, b"IDAT", &zlib_stored(&raw))?;
+        write_chunk(w, b"IEND", &[])
+    }
+
+}
+
+/// Reverse the order of the rows of `pixels`, in place
+pub fn flip_rows(pixels: &mut [u8], stride: usize) {
+    let rows = pixels.len() / stride;
+    for i in 0..rows / 2 {
+        let (top, bottom) = pixels.split_at_mut((rows - 1 - i) * stride);
+        top[i * stride..(i + 1) * stride].swap_with_slice(&mut bottom[..stride]);
+    }
+}
+
+/// `dir/screenshot-YYYYMMDD-HHMMSS-mmm.ext`, in UTC. The directory is created if needed.
+pub fn timestamped_path(dir: &Path, ext: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = now.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let rem = secs % 86400;
+    Ok(dir.join(format!("screenshot-{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}.{}",
+        year, month, day, rem / 3600, rem / 60 % 60, rem % 60, now.subsec_nanos() / 1_000_000, ext)))
+}
+
+/// Days since 1970-01-01 to (year, month, day), from Howard Hinnant's date algorithms
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn be32(v: u32) -> [u8; 4] {
+    [(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8]
+}
+
+fn write_chunk<W: Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&be32(data.len() as u32))?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    let crc = crc32(kind.iter().chain(data.iter()));
+    w.write_all(&be32(crc))
+}
+
+/// zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(65535).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = if blocks.peek().is_none() { 1 } else { 0 };
+        let len = block.len() as u16;
+        out.push(last);
+        out.extend_from_slice(&[len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8]);
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&be32(adler32(data)));
+    out
+}
+
+fn crc32<'a, I: Iterator<Item = &'a u8>>(bytes: I) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &x in chunk {
+            a += x as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32) -> Image {
+        let pixels = (0..width * height * 4).map(|i| i as u8).collect();
+        Image { width, height, pixels }
+    }
+
+    fn be32_at(bytes: &[u8], at: usize) -> u32 {
+        (bytes[at] as u32) << 24 | (bytes[at + 1] as u32) << 16 | (bytes[at + 2] as u32) << 8 | bytes[at + 3] as u32
+    }
+
+    /// (kind, data) of every chunk, after checking its CRC
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let mut chunks = vec![];
+        let mut at = 8;
+        while at < png.len() {
+            let len = be32_at(png, at) as usize;
+            let body = &png[at + 4..at + 8 + len];
+            assert_eq!(be32_at(png, at + 8 + len), crc32(body.iter()), "CRC of {:?}", String::from_utf8_lossy(&body[..4]));
+            let mut kind = [0; 4];
+            kind.copy_from_slice(&body[..4]);
+            chunks.push((kind, body[4..].to_vec()));
+            at += 12 + len;
+        }
+        chunks
+    }
+
+    #[test]
+    fn flip_rows_reverses_rows() {
+        // 2x3 RGBA, one byte value per row
+        let mut pixels: Vec<u8> = [1u8, 2, 3].iter().flat_map(|&v| vec![v; 8]).collect();
+        flip_rows(&mut pixels, 8);
+        let expected: Vec<u8> = [3u8, 2, 1].iter().flat_map(|&v| vec![v; 8]).collect();
+        assert_eq!(pixels, expected);
+
+        let img = Image::from_gl_rows(2, 3, expected);
+        assert_eq!(&img.pixels[..8], &[1; 8]);
+    }
+
+    #[test]
+    fn ppm_drops_alpha() {
+        let mut out = vec![];
+        image(2, 3).write_ppm(&mut out).unwrap();
+        let header = b"P6\n2 3\n255\n";
+        assert_eq!(&out[..header.len()], header);
+        let body: Vec<u8> = (0..24u8).filter(|i| i % 4 != 3).collect();
+        assert_eq!(&out[header.len()..], &body[..]);
+    }
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b"IEND".iter()), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn png_spans_several_stored_blocks() {
+        // 200 rows of 401 bytes do not fit in one 65535 byte block
+        let img = image(100, 200);
+        let mut png = vec![];
+        img.write_png(&mut png).unwrap();
+
+        let chunks = chunks(&png);
+        let kinds: Vec<&[u8]> = chunks.iter().map(|c| &c.0[..]).collect();
+        assert_eq!(kinds, vec![&b"IHDR"[..], b"IDAT", b"IEND"]);
+
+        let ihdr = &chunks[0].1;
+        assert_eq!(be32_at(ihdr, 0), 100);
+        assert_eq!(be32_at(ihdr, 4), 200);
+        assert_eq!(&ihdr[8..], &[8, 6, 0, 0, 0]);
+        assert!(chunks[2].1.is_empty());
+
+        let raw: Vec<u8> = img.pixels.chunks(400).flat_map(|row| Some(0).into_iter().chain(row.iter().cloned())).collect();
+        assert_eq!(raw.len(), 80200);
+
+        let zlib = &chunks[1].1;
+        assert_eq!(&zlib[..2], &[0x78, 0x01]);
+        let mut data = vec![];
+        let mut at = 2;
+        let mut lens = vec![];
+        loop {
+            let last = zlib[at];
+            let len = zlib[at + 1] as u16 | (zlib[at + 2] as u16) << 8;
+            let nlen = zlib[at + 3] as u16 | (zlib[at + 4] as u16) << 8;
+            assert_eq!(nlen, !len);
+            data.extend_from_slice(&zlib[at + 5..at + 5 + len as usize]);
+            lens.push(len);
+            at += 5 + len as usize;
+            if last == 1 {
+                break;
+            }
+            assert_eq!(last, 0);
+        }
+        assert_eq!(lens, vec![65535, 14665]);
+        assert_eq!(data, raw);
+        assert_eq!(zlib.len(), at + 4);
+        assert_eq!(be32_at(zlib, at), adler32(&raw));
+    }
+
+    #[test]
+    fn timestamped_path_format() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+
+        let dir = std::env::temp_dir().join(format!("rustcg-capture-{}", std::process::id()));
+        let path = timestamped_path(&dir, "png").unwrap();
+        assert!(dir.is_dir());
+        let name = path.file_name().unwrap().to_str().unwrap();
+        // screenshot-YYYYMMDD-HHMMSS-mmm.png
+        assert_eq!(name.len(), 34);
+        assert!(name.starts_with("screenshot-") && name.ends_with(".png"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
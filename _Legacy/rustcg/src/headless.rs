@@ -0,0 +1,238 @@
+//! A GL 3.3 core context with no window, for `GameContext::headless`.
+//!
+//! On Linux it is an EGL context on Mesa's surfaceless platform, so it needs neither an X nor a Wayland
+//! display and runs in CI with the software llvmpipe driver. Drivers without the surfaceless platform
+//! get the default EGL display instead, and a 1x1 pbuffer if they cannot make a context current
+//! without a surface. libEGL is loaded at runtime, so it is only required when a headless context is created.
+//!
+//! Elsewhere it falls back to a glutin headless context, which needs an events loop and so a display.
+
+#[cfg(target_os = "linux")]
+pub use self::egl::HeadlessContext;
+#[cfg(not(target_os = "linux"))]
+pub use self::glutin_context::HeadlessContext;
+
+#[cfg(target_os = "linux")]
+mod egl {
+    use std::ffi::CString;
+    use std::mem;
+    use std::os::raw::{c_char, c_void};
+    use std::path::Path;
+    use std::ptr;
+
+    use shared_library::dynamic_library::DynamicLibrary;
+
+    type EGLDisplay = *mut c_void;
+    type EGLConfig = *mut c_void;
+    type EGLContext = *mut c_void;
+    type EGLSurface = *mut c_void;
+    type EGLint = i32;
+    type EGLBoolean = u32;
+    type EGLenum = u32;
+
+    const NONE: EGLint = 0x3038;
+    const SURFACE_TYPE: EGLint = 0x3033;
+    const PBUFFER_BIT: EGLint = 0x0001;
+    const RENDERABLE_TYPE: EGLint = 0x3040;
+    const OPENGL_BIT: EGLint = 0x0008;
+    const WIDTH: EGLint = 0x3057;
+    const HEIGHT: EGLint = 0x3056;
+    const OPENGL_API: EGLenum = 0x30A2;
+    const CONTEXT_MAJOR_VERSION: EGLint = 0x3098;
+    const CONTEXT_MINOR_VERSION: EGLint = 0x30FB;
+    const CONTEXT_OPENGL_PROFILE_MASK: EGLint = 0x30FD;
+    const CONTEXT_OPENGL_CORE_PROFILE_BIT: EGLint = 0x0001;
+    const PLATFORM_SURFACELESS_MESA: EGLenum = 0x31DD;
+
+    /// The entry points we use, loaded from libEGL
+    struct Egl {
+        get_proc_address: unsafe extern "C" fn(*const c_char) -> *const c_void,
+        get_display: unsafe extern "C" fn(*mut c_void) -> EGLDisplay,
+        initialize: unsafe extern "C" fn(EGLDisplay, *mut EGLint, *mut EGLint) -> EGLBoolean,
+        terminate: unsafe extern "C" fn(EGLDisplay) -> EGLBoolean,
+        bind_api: unsafe extern "C" fn(EGLenum) -> EGLBoolean,
+        choose_config: unsafe extern "C" fn(EGLDisplay, *const EGLint, *mut EGLConfig, EGLint, *mut EGLint) -> EGLBoolean,
+        create_context: unsafe extern "C" fn(EGLDisplay, EGLConfig, EGLContext, *const EGLint) -> EGLContext,
+        destroy_context: unsafe extern "C" fn(EGLDisplay, EGLContext) -> EGLBoolean,
+        create_pbuffer_surface: unsafe extern "C" fn(EGLDisplay, EGLConfig, *const EGLint) -> EGLSurface,
+        destroy_surface: unsafe extern "C" fn(EGLDisplay, EGLSurface) -> EGLBoolean,
+        make_current: unsafe extern "C" fn(EGLDisplay, EGLSurface, EGLSurface, EGLContext) -> EGLBoolean,
+        get_error: unsafe extern "C" fn() -> EGLint
+    }
+
+    impl Egl {
+
+        fn load(lib: &DynamicLibrary) -> Result<Egl, String> {
+            unsafe fn symbol<T>(lib: &DynamicLibrary, name: &str) -> Result<T, String> {
+                lib.symbol::<c_void>(name).map(|ptr| mem::transmute_copy(&ptr))
+            }
+            unsafe {
+                Ok(Egl {
+                    get_proc_address: symbol(lib, "eglGetProcAddress")?,
+                    get_display: symbol(lib, "eglGetDisplay")?,
+                    initialize: symbol(lib, "eglInitialize")?,
+                    terminate: symbol(lib, "eglTerminate")?,
+                    bind_api: symbol(lib, "eglBindAPI")?,
+                    choose_config: symbol(lib, "eglChooseConfig")?,
+                    create_context: symbol(lib, "eglCreateContext")?,
+                    destroy_context: symbol(lib, "eglDestroyContext")?,
+                    create_pbuffer_surface: symbol(lib, "eglCreatePbufferSurface")?,
+                    destroy_surface: symbol(lib, "eglDestroySurface")?,
+                    make_current: symbol(lib, "eglMakeCurrent")?,
+                    get_error: symbol(lib, "eglGetError")?
+                })
+            }
+        }
+
+        fn proc_address(&self, name: &str) -> *const c_void {
+            let name = CString::new(name).unwrap();
+            unsafe { (self.get_proc_address)(name.as_ptr()) }
+        }
+
+        fn error(&self, call: &str) -> String {
+            format!("{} failed with EGL error 0x{:x}", call, unsafe { (self.get_error)() })
+        }
+
+        /// The surfaceless platform if the driver has it, otherwise the default display
+        unsafe fn display(&self) -> EGLDisplay {
+            let get_platform_display = self.proc_address("eglGetPlatformDisplayEXT");
+            if !get_platform_display.is_null() {
+                let get_platform_display: unsafe extern "C" fn(EGLenum, *mut c_void, *const EGLint) -> EGLDisplay =
+                    mem::transmute(get_platform_display);
+                let display = get_platform_display(PLATFORM_SURFACELESS_MESA, ptr::null_mut(), ptr::null());
+                if !display.is_null() {
+                    return display;
+                }
+            }
+            (self.get_display)(ptr::null_mut())
+        }
+
+    }
+
+    pub struct HeadlessContext {
+        egl: Egl,
+        display: EGLDisplay,
+        context: EGLContext,
+        /// Null unless the driver cannot make a context current without a surface
+        surface: EGLSurface,
+        /// Keeps the entry points in `egl` valid
+        _lib: DynamicLibrary
+    }
+
+    impl HeadlessContext {
+
+        /// Create an OpenGL 3.3 core context and make it current on this thread.
+        pub fn new() -> Result<HeadlessContext, String> {
+            let lib = DynamicLibrary::open(Some(Path::new("libEGL.so.1")))
+                .map_err(|e| format!("cannot load libEGL.so.1: {}", e))?;
+            let egl = Egl::load(&lib)?;
+
+            unsafe {
+                let display = egl.display();
+                if display.is_null() || (egl.initialize)(display, ptr::null_mut(), ptr::null_mut()) == 0 {
+                    return Err(egl.error("eglInitialize"));
+                }
+                // From here on Drop cleans up whatever has been created
+                let mut ctx = HeadlessContext {
+                    egl,
+                    display,
+                    context: ptr::null_mut(),
+                    surface: ptr::null_mut(),
+                    _lib: lib
+                };
+                if (ctx.egl.bind_api)(OPENGL_API) == 0 {
+                    return Err(ctx.egl.error("eglBindAPI"));
+                }
+
+                let config_attribs = [SURFACE_TYPE, PBUFFER_BIT, RENDERABLE_TYPE, OPENGL_BIT, NONE];
+                let mut config = ptr::null_mut();
+                let mut count = 0;
+                if (ctx.egl.choose_config)(display, config_attribs.as_ptr(), &mut config, 1, &mut count) == 0 || count == 0 {
+                    return Err(ctx.egl.error("eglChooseConfig"));
+                }
+
+                let context_attribs = [
+                    CONTEXT_MAJOR_VERSION, 3,
+                    CONTEXT_MINOR_VERSION, 3,
+                    CONTEXT_OPENGL_PROFILE_MASK, CONTEXT_OPENGL_CORE_PROFILE_BIT,
+                    NONE
+                ];
+                ctx.context = (ctx.egl.create_context)(display, config, ptr::null_mut(), context_attribs.as_ptr());
+                if ctx.context.is_null() {
+                    return Err(ctx.egl.error("eglCreateContext"));
+                }
+
+                // Everything is drawn into framebuffer objects, so no surface is needed where the driver allows it
+                if (ctx.egl.make_current)(display, ptr::null_mut(), ptr::null_mut(), ctx.context) == 0 {
+                    let pbuffer_attribs = [WIDTH, 1, HEIGHT, 1, NONE];
+                    ctx.surface = (ctx.egl.create_pbuffer_surface)(display, config, pbuffer_attribs.as_ptr());
+                    if ctx.surface.is_null() {
+                        return Err(ctx.egl.error("eglCreatePbufferSurface"));
+                    }
+                    if (ctx.egl.make_current)(display, ctx.surface, ctx.surface, ctx.context) == 0 {
+                        return Err(ctx.egl.error("eglMakeCurrent"));
+                    }
+                }
+                Ok(ctx)
+            }
+        }
+
+        pub fn get_proc_address(&self, name: &str) -> *const c_void {
+            self.egl.proc_address(name)
+        }
+
+    }
+
+    impl Drop for HeadlessContext {
+
+        fn drop(&mut self) {
+            unsafe {
+                (self.egl.make_current)(self.display, ptr::null_mut(), ptr::null_mut(), ptr::null_mut());
+                if !self.surface.is_null() {
+                    (self.egl.destroy_surface)(self.display, self.surface);
+                }
+                if !self.context.is_null() {
+                    (self.egl.destroy_context)(self.display, self.context);
+                }
+                (self.egl.terminate)(self.display);
+            }
+        }
+
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod glutin_context {
+    use std::os::raw::c_void;
+
+    use glutin::{self, GlContext, GlProfile};
+
+    pub struct HeadlessContext {
+        context: glutin::Context,
+        _events_loop: glutin::EventsLoop
+    }
+
+    impl HeadlessContext {
+
+        /// Create an OpenGL 3.3 core context and make it current on this thread.
+        pub fn new() -> Result<HeadlessContext, String> {
+            let events_loop = glutin::EventsLoop::new();
+            let gl_context = glutin::ContextBuilder::new()
+                .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (3, 3)))
+                .with_gl_profile(GlProfile::Core);
+            let context = glutin::Context::new(&events_loop, gl_context, false).map_err(|e| e.to_string())?;
+            unsafe {
+                context.make_current().map_err(|e| format!("{:?}", e))?;
+            }
+            Ok(HeadlessContext {
+                context,
+                _events_loop: events_loop
+            })
+        }
+
+        pub fn get_proc_address(&self, name: &str) -> *const c_void {
+            self.context.get_proc_address(name) as *const _
+        }
+
+    }
+}
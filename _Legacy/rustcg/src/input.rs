@@ -0,0 +1,259 @@
+use std::collections::HashSet;
+
+use glutin::{ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+/// Touchpads report scrolling in pixels; convert it to lines so both kinds of devices look alike.
+const PIXELS_PER_LINE: f32 = 20.0;
+
+/// Keyboard and mouse state, available through `GameContext::input`.
+///
+/// "Pressed" and "released" only hold for the frame in which the change happened, while "down"
+/// holds for as long as the key or button is held. The window feeds it through `handle_event`;
+/// the `key`, `mouse_button`, `mouse_moved` and `scroll` methods inject events directly,
+/// which is how it can be driven without a window.
+#[derive(Default, Debug)]
+pub struct Input {
+    keys_down: HashSet<VirtualKeyCode>,
+    keys_pressed: HashSet<VirtualKeyCode>,
+    keys_released: HashSet<VirtualKeyCode>,
+    buttons_down: HashSet<MouseButton>,
+    buttons_pressed: HashSet<MouseButton>,
+    buttons_released: HashSet<MouseButton>,
+    mouse_position: Option<(f32, f32)>,
+    mouse_delta: (f32, f32),
+    scroll_delta: (f32, f32)
+}
+
+impl Input {
+
+    pub fn new() -> Input {
+        Input::default()
+    }
+
+    /// Forget the per-frame state (pressed, released, deltas). Called at the start of each frame,
+    /// before the events of that frame are handled.
+    pub fn begin_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.mouse_delta = (0.0, 0.0);
+        self.scroll_delta = (0.0, 0.0);
+    }
+
+    /// Update the state from a window event. Positions are converted to physical pixels with `dpi_factor`,
+    /// the same unit as `GameContext::get_window_size`.
+    pub fn handle_event(&mut self, event: &WindowEvent, dpi_factor: f64) {
+        match *event {
+            WindowEvent::KeyboardInput { input, .. } => {
+                if let Some(key) = input.virtual_keycode {
+                    self.key(key, input.state == ElementState::Pressed);
+                }
+            },
+            WindowEvent::MouseInput { state, button, .. } =>
+                self.mouse_button(button, state == ElementState::Pressed),
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = position.to_physical(dpi_factor);
+                self.mouse_moved(position.x as f32, position.y as f32);
+            },
+            WindowEvent::CursorLeft { .. } => self.mouse_position = None,
+            WindowEvent::MouseWheel { delta, .. } => match delta {
+                MouseScrollDelta::LineDelta(x, y) => self.scroll(x, y),
+                MouseScrollDelta::PixelDelta(pos) =>
+                    self.scroll(pos.x as f32 / PIXELS_PER_LINE, pos.y as f32 / PIXELS_PER_LINE)
+            },
+            // Release events are lost while the window is not focused, so don't leave keys stuck down
+            WindowEvent::Focused(false) => {
+                self.keys_released.extend(self.keys_down.drain());
+                self.buttons_released.extend(self.buttons_down.drain());
+            },
+            _ => ()
+        }
+    }
+
+    pub fn key(&mut self, key: VirtualKeyCode, pressed: bool) {
+        if pressed {
+            // Key repeat sends more press events while the key is held; only the first one counts
+            if self.keys_down.insert(key) {
+                self.keys_pressed.insert(key);
+            }
+        } else if self.keys_down.remove(&key) {
+            self.keys_released.insert(key);
+        }
+    }
+
+    pub fn mouse_button(&mut self, button: MouseButton, pressed: bool) {
+        if pressed {
+            if self.buttons_down.insert(button) {
+                self.buttons_pressed.insert(button);
+            }
+        } else if self.buttons_down.remove(&button) {
+            self.buttons_released.insert(button);
+        }
+    }
+
+    /// Move the cursor to (x, y) in physical pixels from the top-left corner of the window.
+    pub fn mouse_moved(&mut self, x: f32, y: f32) {
+        // Entering the window is not a movement
+        if let Some((last_x, last_y)) = self.mouse_position {
+            self.mouse_delta.0 += x - last_x;
+            self.mouse_delta.1 += y - last_y;
+        }
+        self.mouse_position = Some((x, y));
+    }
+
+    /// Scroll by (x, y) lines. Positive y scrolls up.
+    pub fn scroll(&mut self, x: f32, y: f32) {
+        self.scroll_delta.0 += x;
+        self.scroll_delta.1 += y;
+    }
+
+    pub fn is_key_down(&self, key: VirtualKeyCode) -> bool {
+        self.keys_down.contains(&key)
+    }
+
+    pub fn is_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    pub fn is_key_released(&self, key: VirtualKeyCode) -> bool {
+        self.keys_released.contains(&key)
+    }
+
+    pub fn is_button_down(&self, button: MouseButton) -> bool {
+        self.buttons_down.contains(&button)
+    }
+
+    pub fn is_button_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    pub fn is_button_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+
+    /// Cursor position in physical pixels, None when the cursor is outside the window.
+    pub fn mouse_position(&self) -> Option<(f32, f32)> {
+        self.mouse_position
+    }
+
+    /// Cursor movement during this frame.
+    pub fn mouse_delta(&self) -> (f32, f32) {
+        self.mouse_delta
+    }
+
+    /// Scrolling during this frame, in lines.
+    pub fn scroll_delta(&self) -> (f32, f32) {
+        self.scroll_delta
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glutin;
+
+    #[test]
+    fn pressed_and_released_last_one_frame() {
+        let mut input = Input::new();
+        input.key(VirtualKeyCode::Space, true);
+        assert!(input.is_key_pressed(VirtualKeyCode::Space));
+        assert!(!input.is_key_released(VirtualKeyCode::Space));
+
+        input.begin_frame();
+        assert!(!input.is_key_pressed(VirtualKeyCode::Space));
+        assert!(input.is_key_down(VirtualKeyCode::Space));
+
+        input.key(VirtualKeyCode::Space, false);
+        assert!(input.is_key_released(VirtualKeyCode::Space));
+        assert!(!input.is_key_down(VirtualKeyCode::Space));
+
+        input.begin_frame();
+        assert!(!input.is_key_released(VirtualKeyCode::Space));
+    }
+
+    #[test]
+    fn held_key_ignores_repeat() {
+        let mut input = Input::new();
+        input.key(VirtualKeyCode::W, true);
+        for _ in 0..3 {
+            input.begin_frame();
+            input.key(VirtualKeyCode::W, true);
+            assert!(input.is_key_down(VirtualKeyCode::W));
+            assert!(!input.is_key_pressed(VirtualKeyCode::W));
+        }
+    }
+
+    #[test]
+    fn mouse_delta_between_moves() {
+        let mut input = Input::new();
+        input.mouse_moved(10.0, 20.0);
+        assert_eq!(input.mouse_delta(), (0.0, 0.0));
+        input.mouse_moved(13.0, 18.0);
+        input.mouse_moved(15.0, 25.0);
+        assert_eq!(input.mouse_delta(), (5.0, 5.0));
+        assert_eq!(input.mouse_position(), Some((15.0, 25.0)));
+
+        input.begin_frame();
+        assert_eq!(input.mouse_delta(), (0.0, 0.0));
+        input.mouse_moved(16.0, 25.0);
+        assert_eq!(input.mouse_delta(), (1.0, 0.0));
+    }
+
+    #[test]
+    fn scroll_accumulates_within_a_frame() {
+        let mut input = Input::new();
+        input.scroll(0.0, 1.0);
+        input.scroll(0.5, 2.0);
+        assert_eq!(input.scroll_delta(), (0.5, 3.0));
+
+        input.begin_frame();
+        assert_eq!(input.scroll_delta(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn window_events() {
+        let device_id = unsafe { glutin::DeviceId::dummy() };
+        let key = |state| WindowEvent::KeyboardInput {
+            device_id,
+            input: glutin::KeyboardInput {
+                scancode: 0,
+                state,
+                virtual_keycode: Some(VirtualKeyCode::A),
+                modifiers: glutin::ModifiersState::default()
+            }
+        };
+        let moved = |x, y| WindowEvent::CursorMoved {
+            device_id,
+            position: glutin::dpi::LogicalPosition::new(x, y),
+            modifiers: glutin::ModifiersState::default()
+        };
+
+        let mut input = Input::new();
+        input.handle_event(&key(ElementState::Pressed), 2.0);
+        assert!(input.is_key_pressed(VirtualKeyCode::A));
+
+        // Positions are in physical pixels
+        input.handle_event(&moved(10.0, 5.0), 2.0);
+        input.handle_event(&moved(11.0, 5.0), 2.0);
+        assert_eq!(input.mouse_position(), Some((22.0, 10.0)));
+        assert_eq!(input.mouse_delta(), (2.0, 0.0));
+
+        // Losing focus releases everything that is held
+        input.begin_frame();
+        input.handle_event(&WindowEvent::Focused(false), 2.0);
+        assert!(input.is_key_released(VirtualKeyCode::A));
+        assert!(!input.is_key_down(VirtualKeyCode::A));
+    }
+
+    #[test]
+    fn click_within_one_frame() {
+        let mut input = Input::new();
+        input.mouse_button(MouseButton::Left, true);
+        input.mouse_button(MouseButton::Left, false);
+        assert!(input.is_button_pressed(MouseButton::Left));
+        assert!(input.is_button_released(MouseButton::Left));
+        assert!(!input.is_button_down(MouseButton::Left));
+    }
+}
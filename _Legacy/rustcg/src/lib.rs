@@ -1,23 +1,231 @@
 extern crate gfx;
 extern crate glutin;
 extern crate gfx_device_gl;
+extern crate gfx_window_glutin;
+extern crate gfx_gl;
+#[cfg(target_os = "linux")]
+extern crate shared_library;
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Instant;
 
 use glutin::dpi::LogicalSize;
 
 use gfx::format::Rgba8;
 use gfx::format::DepthStencil;
+use gfx::format::R8_G8_B8_A8;
 use gfx::Device;
+use gfx::Factory;
+use gfx::traits::FactoryExt;
 use gfx::handle::RenderTargetView;
 use gfx::handle::DepthStencilView;
+use gfx::handle::Texture;
+use gfx::memory::Typed;
 
 use gfx_device_gl::Resources;
 
+pub mod capture;
+pub mod preprocess;
 pub mod resource;
+pub mod shader;
+mod headless;
+mod input;
+mod time;
+
+pub use capture::Image;
+pub use glutin::{GlProfile, MouseButton, VirtualKeyCode};
+pub use input::Input;
+pub use resource::{AssetError, AssetManager};
+pub use shader::{ShaderError, ShaderProgram};
+pub use time::FrameTime;
 
+/// A game driven by `run`.
 pub trait Game {
+    /// Called once after the context is created, before the first frame.
     fn init(&mut self, ctx: &mut GameContext);
 
+    /// Called once per frame, after events are processed and before buffers are swapped.
     fn update(&mut self, ctx: &mut GameContext);
+
+    /// Called zero or more times per frame before `update`, once per fixed step,
+    /// when `ContextConfig::fixed_timestep` is set.
+    fn fixed_update(&mut self, _ctx: &mut GameContext) {}
+
+    /// Called once when the loop exits, either by closing the window or by `GameContext::exit`.
+    fn shutdown(&mut self, _ctx: &mut GameContext) {}
+}
+
+/// Window and loop settings used when creating a `GameContext`.
+#[derive(Clone, Debug)]
+pub struct ContextConfig {
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+    pub resizable: bool,
+    /// Fullscreen on the primary monitor.
+    pub fullscreen: bool,
+    /// Render offscreen at `width` x `height` instead of opening a window, see `GameContext::headless`.
+    /// The other window and GL settings are ignored.
+    pub headless: bool,
+    pub vsync: bool,
+    /// MSAA sample count, 0 disables multisampling. Must be 0 or a power of two.
+    pub samples: u16,
+    /// Requested OpenGL version, e.g. (3, 3). None asks for the latest version available.
+    pub gl_version: Option<(u8, u8)>,
+    /// None leaves the choice of profile to the driver.
+    pub gl_profile: Option<GlProfile>,
+    /// Length in seconds of a `Game::fixed_update` step. None disables fixed-timestep updates.
+    pub fixed_timestep: Option<f32>,
+    /// Upper bound of frames per second in `run`. None runs as fast as buffer swapping allows.
+    pub max_fps: Option<u32>,
+    /// Key that makes `run` save a screenshot into `screenshot_dir`. None, the default, disables it.
+    pub screenshot_key: Option<VirtualKeyCode>,
+    /// Where `run` saves screenshots.
+    pub screenshot_dir: PathBuf
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        ContextConfig {
+            title: "WeAthFolD's gfx playground".to_string(),
+            width: 1280,
+            height: 720,
+            resizable: true,
+            fullscreen: false,
+            headless: false,
+            vsync: false,
+            samples: 0,
+            gl_version: None,
+            gl_profile: None,
+            fixed_timestep: None,
+            max_fps: None,
+            screenshot_key: None,
+            screenshot_dir: PathBuf::from("screenshots")
+        }
+    }
+}
+
+impl ContextConfig {
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn resizable(mut self, resizable: bool) -> Self {
+        self.resizable = resizable;
+        self
+    }
+
+    pub fn fullscreen(mut self, fullscreen: bool) -> Self {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    pub fn headless(mut self, headless: bool) -> Self {
+        self.headless = headless;
+        self
+    }
+
+    pub fn vsync(mut self, vsync: bool) -> Self {
+        self.vsync = vsync;
+        self
+    }
+
+    pub fn samples(mut self, samples: u16) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    pub fn gl_version(mut self, major: u8, minor: u8) -> Self {
+        self.gl_version = Some((major, minor));
+        self
+    }
+
+    pub fn gl_profile(mut self, profile: GlProfile) -> Self {
+        self.gl_profile = Some(profile);
+        self
+    }
+
+    /// Call `Game::fixed_update` at `hz` steps per second.
+    pub fn fixed_timestep(mut self, hz: u32) -> Self {
+        assert!(hz > 0, "fixed timestep rate must be positive");
+        self.fixed_timestep = Some(1.0 / hz as f32);
+        self
+    }
+
+    pub fn max_fps(mut self, fps: u32) -> Self {
+        assert!(fps > 0, "frame cap must be positive");
+        self.max_fps = Some(fps);
+        self
+    }
+
+    pub fn screenshot_key(mut self, key: VirtualKeyCode) -> Self {
+        self.screenshot_key = Some(key);
+        self
+    }
+
+    pub fn screenshot_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.screenshot_dir = dir.into();
+        self
+    }
+
+}
+
+/// Why a `GameContext` could not be created.
+#[derive(Debug)]
+pub enum ContextError {
+    /// The config asks for something that can never work, e.g. 3 MSAA samples.
+    InvalidConfig(String),
+    /// The window or the GL context could not be created.
+    Creation(glutin::CreationError),
+    /// Offscreen render targets could not be created or read back.
+    Offscreen(String),
+    /// A captured frame could not be written.
+    Io(io::Error)
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ContextError::InvalidConfig(ref msg) => write!(f, "invalid context config: {}", msg),
+            ContextError::Creation(ref e) => write!(f, "failed to create window: {}", e),
+            ContextError::Offscreen(ref msg) => write!(f, "offscreen rendering failed: {}", msg),
+            ContextError::Io(ref e) => write!(f, "failed to save frame: {}", e)
+        }
+    }
+}
+
+impl error::Error for ContextError {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            ContextError::Creation(ref e) => Some(e),
+            ContextError::Io(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<glutin::CreationError> for ContextError {
+    fn from(e: glutin::CreationError) -> ContextError {
+        ContextError::Creation(e)
+    }
+}
+
+impl From<io::Error> for ContextError {
+    fn from(e: io::Error) -> ContextError {
+        ContextError::Io(e)
+    }
 }
 
 pub struct GfxContext {
@@ -27,26 +235,64 @@ pub struct GfxContext {
     pub depth_view: DepthStencilView<Resources, DepthStencil>
 }
 
+/// Where the frames go.
+enum Surface {
+    Window {
+        window: glutin::GlWindow,
+        events_loop: glutin::EventsLoop
+    },
+    /// Rendering into `color`, which `read_pixels` copies back.
+    Headless {
+        _context: headless::HeadlessContext,
+        color: Texture<Resources, R8_G8_B8_A8>,
+        width: u32,
+        height: u32
+    }
+}
+
 pub struct GameContext {
     pub gfx: GfxContext,
-    window: glutin::GlWindow,
-    events_loop: glutin::EventsLoop
+    pub assets: AssetManager,
+    surface: Surface,
+    running: bool,
+    time: FrameTime,
+    input: Input
 }
 
 impl GameContext {
 
-    pub fn init() -> GameContext {
+    /// Open a window as described by `config`, or create a headless context if `config.headless` is set.
+    pub fn init(config: ContextConfig) -> Result<GameContext, ContextError> {
+        if config.headless {
+            return GameContext::headless(config.width, config.height);
+        }
+        if config.samples != 0 && !config.samples.is_power_of_two() {
+            return Err(ContextError::InvalidConfig(
+                format!("MSAA sample count must be 0 or a power of two, got {}", config.samples)));
+        }
+
         let events_loop = glutin::EventsLoop::new();
+        let monitor = if config.fullscreen { Some(events_loop.get_primary_monitor()) } else { None };
         let window_builder = glutin::WindowBuilder::new()
-            .with_title("WeAthFolD's gfx playground")
-            .with_dimensions(LogicalSize::new(1280.0, 720.0));
+            .with_title(config.title)
+            .with_dimensions(LogicalSize::new(config.width as f64, config.height as f64))
+            .with_resizable(config.resizable)
+            .with_fullscreen(monitor);
 
         // GL context
-        let gl_context = glutin::ContextBuilder::new();
+        let mut gl_context = glutin::ContextBuilder::new()
+            .with_vsync(config.vsync)
+            .with_multisampling(config.samples);
+        if let Some(version) = config.gl_version {
+            gl_context = gl_context.with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, version));
+        }
+        if let Some(profile) = config.gl_profile {
+            gl_context = gl_context.with_gl_profile(profile);
+        }
 
         // Setup gfx_window_glutin
         let (window, device, factory, rtv, stv) =
-            gfx_window_glutin::init::<Rgba8, DepthStencil>(window_builder, gl_context, &events_loop).unwrap();
+            gfx_window_glutin::init::<Rgba8, DepthStencil>(window_builder, gl_context, &events_loop)?;
 
         let ctx = GameContext {
             gfx: GfxContext {
@@ -55,35 +301,206 @@ impl GameContext {
                 color_view: rtv,
                 depth_view: stv
             },
-            window,
-            events_loop
+            assets: AssetManager::new(),
+            surface: Surface::Window { window, events_loop },
+            running: true,
+            time: FrameTime::default(),
+            input: Input::new()
         };
 
-        ctx
+        Ok(ctx)
+    }
+
+    /// Create a context without a window, rendering into an offscreen `width` x `height` RGBA target.
+    ///
+    /// Uses a GL 3.3 core context. On Linux it comes from EGL and needs no display at all, so it works in CI
+    /// with Mesa's software llvmpipe driver; elsewhere glutin needs an events loop, so a display is required.
+    /// `process_events` and `frame_end` work as usual and no window events ever arrive. To drive a game
+    /// with `run`, set `ContextConfig::headless` instead and leave the loop with `GameContext::exit`.
+    pub fn headless(width: u32, height: u32) -> Result<GameContext, ContextError> {
+        if width == 0 || height == 0 || width > u16::max_value() as u32 || height > u16::max_value() as u32 {
+            return Err(ContextError::InvalidConfig(format!("invalid offscreen size {}x{}", width, height)));
+        }
+
+        let context = headless::HeadlessContext::new().map_err(ContextError::Offscreen)?;
+        let (device, mut factory) = gfx_device_gl::create(|s| context.get_proc_address(s) as *const _);
+
+        let offscreen = |e: &fmt::Debug| ContextError::Offscreen(format!("{:?}", e));
+        let kind = gfx::texture::Kind::D2(width as u16, height as u16, gfx::texture::AaMode::Single);
+        let bind = gfx::memory::Bind::RENDER_TARGET | gfx::memory::Bind::SHADER_RESOURCE
+            | gfx::memory::Bind::TRANSFER_SRC | gfx::memory::Bind::TRANSFER_DST;
+        let color = factory.create_texture::<R8_G8_B8_A8>(
+            kind, 1, bind, gfx::memory::Usage::Data, Some(gfx::format::ChannelType::Unorm)).map_err(|e| offscreen(&e))?;
+        let color_view = factory.view_texture_as_render_target::<Rgba8>(&color, 0, None).map_err(|e| offscreen(&e))?;
+        let depth_view = factory.create_depth_stencil_view_only::<DepthStencil>(width as u16, height as u16)
+            .map_err(|e| offscreen(&e))?;
+
+        Ok(GameContext {
+            gfx: GfxContext {
+                device,
+                factory,
+                color_view,
+                depth_view
+            },
+            assets: AssetManager::new(),
+            surface: Surface::Headless {
+                _context: context,
+                color,
+                width,
+                height
+            },
+            running: true,
+            time: FrameTime::default(),
+            input: Input::new()
+        })
     }
 
     pub fn get_window_size(&self) -> (f32, f32) {
-        let size = self.window.get_inner_size().unwrap();
-        let dpi_factor = self.window.get_hidpi_factor();
-        let pixel_size = size.to_physical(dpi_factor);
-        (pixel_size.width as f32, pixel_size.height as f32)
+        match self.surface {
+            Surface::Window { ref window, .. } => {
+                let size = window.get_inner_size().unwrap();
+                let dpi_factor = window.get_hidpi_factor();
+                let pixel_size = size.to_physical(dpi_factor);
+                (pixel_size.width as f32, pixel_size.height as f32)
+            },
+            Surface::Headless { width, height, .. } => (width as f32, height as f32)
+        }
+    }
+
+    pub fn is_headless(&self) -> bool {
+        match self.surface {
+            Surface::Window { .. } => false,
+            Surface::Headless { .. } => true
+        }
+    }
+
+    /// Read back what has been rendered to a headless context so far, as RGBA8 pixels, top row first.
+    /// Commands recorded in an encoder must be flushed before calling this.
+    pub fn read_pixels(&mut self) -> Result<Vec<u8>, ContextError> {
+        let (width, _) = self.get_window_size();
+        let mut pixels = self.read_offscreen()?;
+        capture::flip_rows(&mut pixels, width as usize * 4);
+        Ok(pixels)
+    }
+
+    /// The offscreen target as RGBA8 pixels in GL order, bottom row first
+    fn read_offscreen(&mut self) -> Result<Vec<u8>, ContextError> {
+        let (color, width, height) = match self.surface {
+            Surface::Headless { ref color, width, height, .. } => (color, width as usize, height as usize),
+            Surface::Window { .. } => return Err(ContextError::Offscreen("read_pixels needs a headless context".to_string()))
+        };
+        let offscreen = |e: &fmt::Debug| ContextError::Offscreen(format!("{:?}", e));
+        let gfx = &mut self.gfx;
+
+        let buffer = gfx.factory.create_download_buffer::<[u8; 4]>(width * height).map_err(|e| offscreen(&e))?;
+        let mut encoder: gfx::Encoder<_, _> = gfx.factory.create_command_buffer().into();
+        let info = color.get_info().to_raw_image_info(gfx::format::ChannelType::Unorm, 0);
+        encoder.copy_texture_to_buffer_raw(color.raw(), None, info, buffer.raw(), 0).map_err(|e| offscreen(&e))?;
+        encoder.flush(&mut gfx.device);
+
+        let reader = gfx.factory.read_mapping(&buffer).map_err(|e| offscreen(&e))?;
+        let mut pixels = Vec::with_capacity(width * height * 4);
+        for pixel in reader.iter() {
+            pixels.extend_from_slice(pixel);
+        }
+        Ok(pixels)
+    }
+
+    /// Read back the current color target: the back buffer of the window, or the offscreen target.
+    ///
+    /// Call it after flushing the frame's encoder and before `frame_end`, since swapping buffers
+    /// leaves the back buffer undefined.
+    pub fn capture_frame(&mut self) -> Result<Image, ContextError> {
+        let (width, height) = self.get_window_size();
+        let (width, height) = (width as u32, height as u32);
+        if self.is_headless() {
+            let pixels = self.read_offscreen()?;
+            return Ok(Image::from_gl_rows(width, height, pixels));
+        }
+
+        let mut pixels = vec![0u8; width as usize * height as usize * 4];
+        unsafe {
+            self.gfx.device.with_gl(|gl| {
+                gl.BindFramebuffer(gfx_gl::READ_FRAMEBUFFER, 0);
+                gl.ReadBuffer(gfx_gl::BACK);
+                // Rows are tightly packed, whatever the width
+                gl.PixelStorei(gfx_gl::PACK_ALIGNMENT, 1);
+                gl.ReadPixels(0, 0, width as i32, height as i32, gfx_gl::RGBA, gfx_gl::UNSIGNED_BYTE,
+                              pixels.as_mut_ptr() as *mut _);
+                gl.PixelStorei(gfx_gl::PACK_ALIGNMENT, 4);
+            });
+        }
+        Ok(Image::from_gl_rows(width, height, pixels))
+    }
+
+    /// Capture the current frame into `dir` as a timestamped PNG. Returns the path of the file.
+    pub fn save_screenshot(&mut self, dir: &Path) -> Result<PathBuf, ContextError> {
+        let image = self.capture_frame()?;
+        let path = capture::timestamped_path(dir, "png")?;
+        image.save(&path)?;
+        Ok(path)
+    }
+
+    /// Timing of the current frame. Only updated by `run`.
+    pub fn time(&self) -> &FrameTime {
+        &self.time
+    }
+
+    /// Keyboard and mouse state as of the last `process_events`.
+    pub fn input(&self) -> &Input {
+        &self.input
+    }
+
+    /// Mutable access to the input state, e.g. for injecting synthetic events.
+    pub fn input_mut(&mut self) -> &mut Input {
+        &mut self.input
+    }
+
+    /// Shorthand for `time().delta`.
+    pub fn delta_time(&self) -> f32 {
+        self.time.delta
+    }
+
+    /// Ask `run` to leave the loop after the current frame.
+    pub fn exit(&mut self) {
+        self.running = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
     }
 
     pub fn process_events(&mut self, running: &mut bool) {
-        let window = &self.window;
+        self.poll_events();
+        if !self.running {
+            *running = false;
+        }
+    }
+
+    fn poll_events(&mut self) {
         let gfx = &mut self.gfx;
-        self.events_loop.poll_events(|event| {
+        let running = &mut self.running;
+        let input = &mut self.input;
+        input.begin_frame();
+        let (window, events_loop) = match self.surface {
+            Surface::Window { ref window, ref mut events_loop } => (window, events_loop),
+            Surface::Headless { .. } => return
+        };
+        events_loop.poll_events(|event| {
             match event {
-                glutin::Event::WindowEvent { event, .. } => match event {
-                    glutin::WindowEvent::CloseRequested => *running = false,
-                    glutin::WindowEvent::Resized(new_size) => {
-                        let dpi_factor = window.get_hidpi_factor();
-                        window.resize(new_size.to_physical(dpi_factor));
-                        // Here resized new views will be created
-                        gfx_window_glutin::update_views(
-                            &window, &mut gfx.color_view, &mut gfx.depth_view);
-                    },
-                    _ => ()
+                glutin::Event::WindowEvent { event, .. } => {
+                    input.handle_event(&event, window.get_hidpi_factor());
+                    match event {
+                        glutin::WindowEvent::CloseRequested => *running = false,
+                        glutin::WindowEvent::Resized(new_size) => {
+                            let dpi_factor = window.get_hidpi_factor();
+                            window.resize(new_size.to_physical(dpi_factor));
+                            // Here resized new views will be created
+                            gfx_window_glutin::update_views(
+                                &window, &mut gfx.color_view, &mut gfx.depth_view);
+                        },
+                        _ => ()
+                    }
                 },
                 _ => ()
             }
@@ -92,9 +509,137 @@ impl GameContext {
 
     pub fn frame_end(&mut self) {
         // Swap buffers
-        self.window.swap_buffers().unwrap();
+        if let Surface::Window { ref window, .. } = self.surface {
+            window.swap_buffers().unwrap();
+        }
         self.gfx.device.cleanup();
     }
 
 }
 
+/// Create a context from `config` and drive `game` until the window is closed or `GameContext::exit` is called.
+pub fn run<G: Game>(config: ContextConfig, mut game: G) -> Result<(), ContextError> {
+    let mut timer = time::FrameTimer::new(config.fixed_timestep, config.max_fps);
+    let screenshot_key = config.screenshot_key;
+    let screenshot_dir = config.screenshot_dir.clone();
+    let mut ctx = GameContext::init(config)?;
+    game.init(&mut ctx);
+    let mut last_frame = Instant::now();
+
+    while ctx.running {
+        ctx.poll_events();
+        if !ctx.running {
+            break;
+        }
+
+        let frame_start = Instant::now();
+        timer.begin_frame(frame_start - last_frame, &mut ctx.time);
+        last_frame = frame_start;
+
+        if let Some(step) = timer.fixed_step() {
+            let frame_delta = ctx.time.delta;
+            ctx.time.delta = step;
+            while ctx.running && timer.consume_step() {
+                game.fixed_update(&mut ctx);
+            }
+            ctx.time.delta = frame_delta;
+            ctx.time.alpha = timer.alpha();
+        }
+
+        game.update(&mut ctx);
+        if screenshot_key.map_or(false, |key| ctx.input.is_key_pressed(key)) {
+            match ctx.save_screenshot(&screenshot_dir) {
+                Ok(path) => println!("[capture] saved {}", path.display()),
+                Err(e) => eprintln!("[capture] failed to save screenshot: {}", e)
+            }
+        }
+        ctx.frame_end();
+
+        if let Some(wait) = timer.frame_wait(frame_start.elapsed()) {
+            thread::sleep(wait);
+        }
+    }
+
+    game.shutdown(&mut ctx);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // These need a GL driver; Mesa's llvmpipe is enough. Run them with `cargo test -- --ignored`.
+
+    #[test]
+    #[ignore]
+    fn headless_clear_reads_back() {
+        let mut ctx = GameContext::headless(4, 3).unwrap();
+        let mut encoder: gfx::Encoder<_, _> = ctx.gfx.factory.create_command_buffer().into();
+        encoder.clear(&ctx.gfx.color_view, [1.0, 0.2, 0.0, 1.0]);
+        encoder.flush(&mut ctx.gfx.device);
+
+        let pixels = ctx.read_pixels().unwrap();
+        assert_eq!(pixels.len(), 4 * 3 * 4);
+        for pixel in pixels.chunks(4) {
+            assert_eq!(pixel, &[255, 51, 0, 255]);
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn headless_read_pixels_puts_top_row_first() {
+        let mut ctx = GameContext::headless(2, 3).unwrap();
+        // GL row 0 is the bottom of the image
+        let rows: Vec<[u8; 4]> = vec![[255, 0, 0, 255], [255, 0, 0, 255],
+                                      [0, 255, 0, 255], [0, 255, 0, 255],
+                                      [0, 0, 255, 255], [0, 0, 255, 255]];
+        {
+            let color = match ctx.surface {
+                Surface::Headless { ref color, .. } => color,
+                Surface::Window { .. } => unreachable!()
+            };
+            let buffer = ctx.gfx.factory.create_buffer_immutable(
+                &rows, gfx::buffer::Role::Staging, gfx::memory::Bind::TRANSFER_SRC).unwrap();
+            let mut encoder: gfx::Encoder<_, _> = ctx.gfx.factory.create_command_buffer().into();
+            let info = color.get_info().to_raw_image_info(gfx::format::ChannelType::Unorm, 0);
+            encoder.copy_buffer_to_texture_raw(buffer.raw(), 0, color.raw(), None, info).unwrap();
+            encoder.flush(&mut ctx.gfx.device);
+        }
+
+        let image = ctx.capture_frame().unwrap();
+        assert_eq!((image.width, image.height), (2, 3));
+        assert_eq!(&image.pixels[..8], &[0, 0, 255, 255, 0, 0, 255, 255]);
+        assert_eq!(&image.pixels[16..], &[255, 0, 0, 255, 255, 0, 0, 255]);
+    }
+
+    #[test]
+    #[ignore]
+    fn run_drives_a_headless_game() {
+        struct Frames {
+            updates: u32,
+            shut_down: bool
+        }
+
+        impl<'a> Game for &'a mut Frames {
+            fn init(&mut self, ctx: &mut GameContext) {
+                assert!(ctx.is_headless());
+            }
+
+            fn update(&mut self, ctx: &mut GameContext) {
+                self.updates += 1;
+                if self.updates == 3 {
+                    ctx.exit();
+                }
+            }
+
+            fn shutdown(&mut self, _ctx: &mut GameContext) {
+                self.shut_down = true;
+            }
+        }
+
+        let mut game = Frames { updates: 0, shut_down: false };
+        run(ContextConfig::default().headless(true).size(16, 16), &mut game).unwrap();
+        assert_eq!(game.updates, 3);
+        assert!(game.shut_down);
+    }
+}
@@ -0,0 +1,266 @@
+use std::error;
+use std::fmt;
+use std::path::PathBuf;
+
+use resource::{AssetError, AssetManager};
+
+/// File name reported for the lines holding the injected `#define`s.
+const DEFINES_FILE: &str = "<defines>";
+/// Index in `ShaderSource::lines` standing for `DEFINES_FILE`
+const DEFINES: usize = usize::max_value();
+
+#[derive(Debug)]
+pub enum PreprocessError {
+    Asset(AssetError),
+    /// Files that include each other; the first name is repeated at the end.
+    IncludeCycle(Vec<String>),
+    /// A malformed directive or a file that is not UTF-8.
+    Syntax { file: String, line: u32, message: String }
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PreprocessError::Asset(ref e) => write!(f, "{}", e),
+            PreprocessError::IncludeCycle(ref chain) => write!(f, "include cycle: {}", chain.join(" -> ")),
+            PreprocessError::Syntax { ref file, line, ref message } => write!(f, "{}:{}: {}", file, line, message)
+        }
+    }
+}
+
+impl error::Error for PreprocessError {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            PreprocessError::Asset(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<AssetError> for PreprocessError {
+    fn from(e: AssetError) -> PreprocessError {
+        PreprocessError::Asset(e)
+    }
+}
+
+/// GLSL after preprocessing, with the origin of every line.
+#[derive(Clone, Debug)]
+pub struct ShaderSource {
+    code: String,
+    /// (file, line) of each line of `code`
+    lines: Vec<(usize, u32)>,
+    /// Names of the files that went into the source, the main file first
+    file_names: Vec<String>,
+    /// Paths of `file_names`
+    files: Vec<PathBuf>
+}
+
+impl ShaderSource {
+
+    fn new() -> ShaderSource {
+        ShaderSource {
+            code: String::new(),
+            lines: vec![],
+            file_names: vec![],
+            files: vec![]
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// Every file the source was built from, the main file first. Each file is listed once.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// File name and line in that file of the 1-based `line` of the preprocessed code.
+    pub fn location(&self, line: u32) -> Option<(&str, u32)> {
+        if line == 0 {
+            return None;
+        }
+        self.lines.get(line as usize - 1).map(|&(file, line)| {
+            let name = if file == DEFINES { DEFINES_FILE } else { self.file_names[file].as_str() };
+            (name, line)
+        })
+    }
+
+    fn push(&mut self, text: &str, file: usize, line: u32) {
+        self.code.push_str(text);
+        self.code.push('\n');
+        self.lines.push((file, line));
+    }
+
+}
+
+/// Load the shader asset `name` and preprocess it:
+///
+/// - `#include "file.glsl"` is replaced by the contents of that asset, looked up in the asset roots
+///   (not relative to the including file). Includes may nest; cycles are an error.
+/// - `defines` are inserted as `#define NAME VALUE` right after the `#version` line of the main file,
+///   or at the very top if it has none.
+///
+/// Files are always read from disk, bypassing the asset cache, so reloading sees the latest version.
+pub fn preprocess(assets: &AssetManager, name: &str, defines: &[(&str, &str)]) -> Result<ShaderSource, PreprocessError> {
+    let mut source = ShaderSource::new();
+    let mut injected = false;
+    expand(assets, name, defines, &mut vec![], &mut source, &mut injected)?;
+
+    if !injected && !defines.is_empty() {
+        let mut with_defines = ShaderSource::new();
+        push_defines(&mut with_defines, defines);
+        source.code = with_defines.code + &source.code;
+        with_defines.lines.extend(source.lines);
+        source.lines = with_defines.lines;
+    }
+    Ok(source)
+}
+
+fn push_defines(source: &mut ShaderSource, defines: &[(&str, &str)]) {
+    for (i, &(name, value)) in defines.iter().enumerate() {
+        source.push(&format!("#define {} {}", name, value), DEFINES, i as u32 + 1);
+    }
+}
+
+fn expand(assets: &AssetManager, name: &str, defines: &[(&str, &str)], stack: &mut Vec<String>,
+          source: &mut ShaderSource, injected: &mut bool) -> Result<(), PreprocessError> {
+    if stack.iter().any(|n| n == name) {
+        let mut chain = stack.clone();
+        chain.push(name.to_string());
+        return Err(PreprocessError::IncludeCycle(chain));
+    }
+
+    let path = assets.find(name)?;
+    let bytes = assets.load_uncached(name)?;
+    let text = String::from_utf8(bytes).map_err(|_| PreprocessError::Syntax {
+        file: name.to_string(),
+        line: 0,
+        message: "file is not valid UTF-8".to_string()
+    })?;
+
+    let file = match source.file_names.iter().position(|n| n == name) {
+        Some(i) => i,
+        None => {
+            source.file_names.push(name.to_string());
+            source.files.push(path);
+            source.file_names.len() - 1
+        }
+    };
+
+    stack.push(name.to_string());
+    for (i, text) in text.lines().enumerate() {
+        let line = i as u32 + 1;
+        let trimmed = text.trim_start();
+        if trimmed.starts_with("#include") {
+            let included = parse_include(&trimmed["#include".len()..]).ok_or_else(|| PreprocessError::Syntax {
+                file: name.to_string(),
+                line,
+                message: format!("expected #include \"file\", found `{}`", trimmed)
+            })?;
+            expand(assets, included, defines, stack, source, injected)?;
+        } else {
+            source.push(text, file, line);
+            // #version must stay the first directive, so the defines go right after it
+            if stack.len() == 1 && !*injected && trimmed.starts_with("#version") {
+                push_defines(source, defines);
+                *injected = true;
+            }
+        }
+    }
+    stack.pop();
+    Ok(())
+}
+
+/// ` "common.glsl"` -> Some("common.glsl")
+fn parse_include(rest: &str) -> Option<&str> {
+    let rest = rest.trim();
+    if rest.len() >= 2 && rest.starts_with('"') && rest.ends_with('"') && !rest[1..rest.len() - 1].contains('"') {
+        Some(&rest[1..rest.len() - 1]).filter(|name| !name.is_empty())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use resource::tests::TempRoot;
+
+    #[test]
+    fn nested_include() {
+        let root = TempRoot::with_files("preprocess-nested", &[
+            ("main.frag", "#version 330 core\n#include \"a.glsl\"\nvoid main() {}\n"),
+            ("a.glsl", "float a;\n  #include \"b.glsl\"\n"),
+            ("b.glsl", "float b;\n")
+        ]);
+        let source = preprocess(&root.assets(), "main.frag", &[]).unwrap();
+        assert_eq!(source.code(), "#version 330 core\nfloat a;\nfloat b;\nvoid main() {}\n");
+        let names: Vec<_> = source.files().iter().map(|p| p.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, ["main.frag", "a.glsl", "b.glsl"]);
+    }
+
+    #[test]
+    fn include_cycles() {
+        let root = TempRoot::with_files("preprocess-cycles", &[
+            ("self.glsl", "#include \"self.glsl\"\n"),
+            ("a.glsl", "#include \"b.glsl\"\n"),
+            ("b.glsl", "#include \"a.glsl\"\n")
+        ]);
+        match preprocess(&root.assets(), "self.glsl", &[]) {
+            Err(PreprocessError::IncludeCycle(chain)) => assert_eq!(chain, ["self.glsl", "self.glsl"]),
+            other => panic!("expected an include cycle, got {:?}", other)
+        }
+        match preprocess(&root.assets(), "a.glsl", &[]) {
+            Err(PreprocessError::IncludeCycle(chain)) => assert_eq!(chain, ["a.glsl", "b.glsl", "a.glsl"]),
+            other => panic!("expected an include cycle, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn defines_go_after_version() {
+        let root = TempRoot::with_files("preprocess-defines", &[
+            ("main.vert", "// header\n#version 330 core\nvoid main() {}\n"),
+            ("bare.vert", "void main() {}\n")
+        ]);
+        let defines = [("FOO", "1"), ("BAR", "vec3(0.0)")];
+
+        let source = preprocess(&root.assets(), "main.vert", &defines).unwrap();
+        assert_eq!(source.code(), "// header\n#version 330 core\n#define FOO 1\n#define BAR vec3(0.0)\nvoid main() {}\n");
+        assert_eq!(source.location(3), Some(("<defines>", 1)));
+        assert_eq!(source.location(5), Some(("main.vert", 3)));
+
+        // Without #version they go first
+        let source = preprocess(&root.assets(), "bare.vert", &defines).unwrap();
+        assert_eq!(source.code(), "#define FOO 1\n#define BAR vec3(0.0)\nvoid main() {}\n");
+        assert_eq!(source.location(3), Some(("bare.vert", 1)));
+    }
+
+    #[test]
+    fn location_across_includes() {
+        let root = TempRoot::with_files("preprocess-location", &[
+            ("main.frag", "#version 330 core\n\n#include \"common.glsl\"\nout vec4 color;\nvoid main() {}\n"),
+            ("common.glsl", "// common\nfloat luma(vec3 c) {\n    return dot(c, vec3(0.3, 0.6, 0.1));\n}\n")
+        ]);
+        let source = preprocess(&root.assets(), "main.frag", &[("DEBUG", "1")]).unwrap();
+        // #version, #define, blank line, 4 lines of common.glsl, then the rest of main.frag
+        assert_eq!(source.location(1), Some(("main.frag", 1)));
+        assert_eq!(source.location(2), Some(("<defines>", 1)));
+        assert_eq!(source.location(3), Some(("main.frag", 2)));
+        assert_eq!(source.location(4), Some(("common.glsl", 1)));
+        assert_eq!(source.location(6), Some(("common.glsl", 3)));
+        assert_eq!(source.location(8), Some(("main.frag", 4)));
+        assert_eq!(source.location(9), Some(("main.frag", 5)));
+        assert_eq!(source.location(0), None);
+        assert_eq!(source.location(10), None);
+    }
+
+    #[test]
+    fn malformed_include() {
+        let root = TempRoot::with_files("preprocess-malformed", &[("main.frag", "#version 330 core\n#include common.glsl\n")]);
+        match preprocess(&root.assets(), "main.frag", &[]) {
+            Err(PreprocessError::Syntax { file, line, .. }) => assert_eq!((file.as_str(), line), ("main.frag", 2)),
+            other => panic!("expected a syntax error, got {:?}", other)
+        }
+    }
+}
@@ -1,6 +1,258 @@
-use std::path::Path;
+use std::collections::HashMap;
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
-pub fn load_bytes(name: &str) -> Vec<u8> {
-    let path = Path::new("./assets/").join(name);
-    return std::fs::read(path).unwrap();
+use preprocess::{self, PreprocessError, ShaderSource};
+
+/// Environment variable with extra asset directories, separated like `PATH`. Searched before anything else.
+pub const ASSETS_ENV: &str = "RUSTCG_ASSETS";
+
+#[derive(Debug)]
+pub enum AssetError {
+    /// No search root contains the asset.
+    NotFound { name: String, searched: Vec<PathBuf> },
+    /// The asset exists but could not be read.
+    Io { path: PathBuf, error: io::Error }
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AssetError::NotFound { ref name, ref searched } => {
+                let searched: Vec<String> = searched.iter().map(|p| p.display().to_string()).collect();
+                write!(f, "asset not found: {}, searched: {}", name, searched.join(", "))
+            },
+            AssetError::Io { ref path, ref error } => write!(f, "failed to read asset {}: {}", path.display(), error)
+        }
+    }
+}
+
+impl error::Error for AssetError {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            AssetError::NotFound { .. } => None,
+            AssetError::Io { ref error, .. } => Some(error)
+        }
+    }
+}
+
+/// Finds assets by name in a list of root directories and caches their bytes.
+///
+/// The default roots are, in order:
+/// - every directory in `$RUSTCG_ASSETS`;
+/// - `assets/` next to the executable, or two levels up, which is the crate root
+///   for binaries in `target/debug/` and `target/release/`;
+/// - `./assets/`, relative to the working directory, which is the crate root under `cargo run`.
+pub struct AssetManager {
+    roots: Vec<PathBuf>,
+    cache: HashMap<String, Rc<Vec<u8>>>
+}
+
+impl Default for AssetManager {
+    fn default() -> Self {
+        AssetManager::with_roots(default_roots())
+    }
+}
+
+impl AssetManager {
+
+    pub fn new() -> AssetManager {
+        AssetManager::default()
+    }
+
+    /// A manager that searches only `roots`, in order.
+    pub fn with_roots(roots: Vec<PathBuf>) -> AssetManager {
+        AssetManager {
+            roots,
+            cache: HashMap::new()
+        }
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Search `root` before the existing roots.
+    pub fn add_root<P: Into<PathBuf>>(&mut self, root: P) {
+        self.roots.insert(0, root.into());
+    }
+
+    /// Where the asset `name` is, e.g. "00-triangle.vert".
+    pub fn find(&self, name: &str) -> Result<PathBuf, AssetError> {
+        for root in &self.roots {
+            let path = root.join(name);
+            if path.is_file() {
+                return Ok(path);
+            }
+        }
+        Err(AssetError::NotFound {
+            name: name.to_string(),
+            searched: self.roots.clone()
+        })
+    }
+
+    /// Bytes of the asset `name`. Read from disk the first time, from the cache afterwards.
+    pub fn load(&mut self, name: &str) -> Result<Rc<Vec<u8>>, AssetError> {
+        if let Some(bytes) = self.cache.get(name) {
+            return Ok(bytes.clone());
+        }
+        let bytes = Rc::new(self.load_uncached(name)?);
+        self.cache.insert(name.to_string(), bytes.clone());
+        Ok(bytes)
+    }
+
+    /// Read the asset from disk, bypassing the cache.
+    pub fn load_uncached(&self, name: &str) -> Result<Vec<u8>, AssetError> {
+        let path = self.find(name)?;
+        fs::read(&path).map_err(|error| AssetError::Io { path, error })
+    }
+
+    /// Load the GLSL asset `name`, resolving `#include`s and injecting `defines`. See `preprocess::preprocess`.
+    pub fn load_shader(&self, name: &str, defines: &[(&str, &str)]) -> Result<ShaderSource, PreprocessError> {
+        preprocess::preprocess(self, name, defines)
+    }
+
+    /// Drop the cached bytes of `name`, so the next `load` reads it again.
+    pub fn invalidate(&mut self, name: &str) {
+        self.cache.remove(name);
+    }
+
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
+}
+
+fn default_roots() -> Vec<PathBuf> {
+    let mut roots = vec![];
+    if let Some(paths) = env::var_os(ASSETS_ENV) {
+        roots.extend(env::split_paths(&paths).filter(|p| !p.as_os_str().is_empty()));
+    }
+    if let Some(dir) = env::current_exe().ok().as_ref().and_then(|exe| exe.parent()) {
+        let crate_root = dir.parent().and_then(Path::parent);
+        for candidate in iter::once(dir).chain(crate_root).map(|d| d.join("assets")) {
+            if candidate.is_dir() && !roots.contains(&candidate) {
+                roots.push(candidate);
+            }
+        }
+    }
+    roots.push(Path::new(".").join("assets"));
+    roots
+}
+
+/// Read the asset `name` from the default roots, without caching.
+pub fn load_bytes(name: &str) -> Result<Vec<u8>, AssetError> {
+    AssetManager::new().load_uncached(name)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::process;
+
+    /// An empty directory under the system temp dir, unique to the test that names it. Deleted on drop.
+    pub struct TempRoot(PathBuf);
+
+    impl TempRoot {
+
+        pub fn new(name: &str) -> TempRoot {
+            let dir = env::temp_dir().join(format!("rustcg-{}-{}", name, process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempRoot(dir)
+        }
+
+        /// A temp root holding `files`, given as (name, contents)
+        pub fn with_files(name: &str, files: &[(&str, &str)]) -> TempRoot {
+            let root = TempRoot::new(name);
+            for &(file, text) in files {
+                root.write(file, text);
+            }
+            root
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.0
+        }
+
+        pub fn write(&self, file: &str, text: &str) {
+            fs::write(self.0.join(file), text).unwrap();
+        }
+
+        /// An asset manager searching only this directory
+        pub fn assets(&self) -> AssetManager {
+            AssetManager::with_roots(vec![self.0.clone()])
+        }
+
+    }
+
+    impl Drop for TempRoot {
+
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+
+    }
+
+    #[test]
+    fn not_found_lists_searched_roots_in_order() {
+        let roots = vec![PathBuf::from("first"), PathBuf::from("second"), PathBuf::from("third")];
+        let assets = AssetManager::with_roots(roots.clone());
+        match assets.find("missing.vert") {
+            Err(AssetError::NotFound { ref name, ref searched }) => {
+                assert_eq!(name, "missing.vert");
+                assert_eq!(searched, &roots);
+            },
+            other => panic!("expected NotFound, got {:?}", other)
+        }
+        assert_eq!(assets.load_uncached("missing.vert").unwrap_err().to_string(),
+                   "asset not found: missing.vert, searched: first, second, third");
+    }
+
+    #[test]
+    fn earlier_roots_win() {
+        let a = TempRoot::with_files("resource-earlier-a", &[("x.glsl", "a")]);
+        let b = TempRoot::with_files("resource-earlier-b", &[("x.glsl", "b"), ("y.glsl", "b")]);
+
+        let mut assets = AssetManager::with_roots(vec![a.path().to_path_buf(), b.path().to_path_buf()]);
+        assert_eq!(assets.load_uncached("x.glsl").unwrap(), b"a");
+        assert_eq!(assets.find("y.glsl").unwrap(), b.path().join("y.glsl"));
+        assets.add_root(b.path().to_path_buf());
+        assert_eq!(assets.load_uncached("x.glsl").unwrap(), b"b");
+    }
+
+    #[test]
+    fn env_roots_are_searched_first() {
+        let root = TempRoot::new("resource-env");
+        env::set_var(ASSETS_ENV, root.path());
+        let roots = default_roots();
+        env::remove_var(ASSETS_ENV);
+        assert_eq!(roots[0], root.path());
+        assert_eq!(roots.last().unwrap(), &Path::new(".").join("assets"));
+        // The executable's dir, two levels above it, and ./assets
+        assert!(roots.len() <= 4, "{:?}", roots);
+    }
+
+    #[test]
+    fn load_is_cached() {
+        let root = TempRoot::with_files("resource-cache", &[("a.frag", "old")]);
+        let mut assets = root.assets();
+
+        let first = assets.load("a.frag").unwrap();
+        root.write("a.frag", "new");
+        let second = assets.load("a.frag").unwrap();
+        assert!(Rc::ptr_eq(&first, &second));
+        assert_eq!(*second, b"old");
+
+        assets.invalidate("a.frag");
+        let third = assets.load("a.frag").unwrap();
+        assert!(!Rc::ptr_eq(&first, &third));
+        assert_eq!(*third, b"new");
+    }
 }
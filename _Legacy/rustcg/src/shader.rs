@@ -0,0 +1,231 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use gfx::pso::{PipelineInit, PipelineState};
+use gfx::shade::ProgramError;
+use gfx::shade::core::CreateShaderError;
+use gfx::traits::FactoryExt;
+use gfx::PipelineStateError;
+
+use gfx_device_gl::{Factory, Resources};
+
+use preprocess::{PreprocessError, ShaderSource};
+use resource::AssetManager;
+use GameContext;
+
+#[derive(Debug)]
+pub enum ShaderError {
+    /// Loading the sources or resolving their `#include`s failed.
+    Preprocess(PreprocessError),
+    /// Compiling, linking or creating the pipeline failed. The log is annotated with file names and lines.
+    Pipeline(String)
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShaderError::Preprocess(ref e) => write!(f, "{}", e),
+            ShaderError::Pipeline(ref log) => write!(f, "{}", log)
+        }
+    }
+}
+
+impl error::Error for ShaderError {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            ShaderError::Preprocess(ref e) => Some(e),
+            ShaderError::Pipeline(_) => None
+        }
+    }
+}
+
+impl From<PreprocessError> for ShaderError {
+    fn from(e: PreprocessError) -> ShaderError {
+        ShaderError::Preprocess(e)
+    }
+}
+
+/// A shader stage and the modification times its files had when it was last read.
+struct Stage {
+    name: String,
+    /// The main file and everything it includes
+    files: Vec<(PathBuf, Option<SystemTime>)>
+}
+
+impl Stage {
+
+    fn new(name: &str, source: &ShaderSource) -> Stage {
+        Stage {
+            name: name.to_string(),
+            files: source.files().iter().map(|p| (p.clone(), modified(p))).collect()
+        }
+    }
+
+    fn changed(&self) -> bool {
+        self.files.iter().any(|&(ref path, time)| modified(path) != time)
+    }
+
+    fn touch(&mut self) {
+        for file in &mut self.files {
+            file.1 = modified(&file.0);
+        }
+    }
+
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// A vertex + fragment shader pipeline loaded from assets, which rebuilds itself when the sources change.
+///
+/// The sources go through `preprocess::preprocess`, so they can `#include` shared code and receive defines.
+/// Call `update` once per frame. It compares the modification times of the source files (including the
+/// included ones) and, if one of them changed, recompiles the program. When that fails the compile log
+/// is printed and `pso` keeps returning the last pipeline that worked, so a typo never takes the example down.
+pub struct ShaderProgram<I: PipelineInit + Clone> {
+    vertex: Stage,
+    fragment: Stage,
+    defines: Vec<(String, String)>,
+    init: I,
+    pso: PipelineState<Resources, I::Meta>
+}
+
+impl<I: PipelineInit + Clone> ShaderProgram<I> {
+
+    /// Load and compile `vs` and `fs` (asset names), failing if the first build fails.
+    pub fn new(ctx: &mut GameContext, vs: &str, fs: &str, init: I) -> Result<ShaderProgram<I>, ShaderError> {
+        ShaderProgram::with_defines(ctx, vs, fs, &[], init)
+    }
+
+    /// Like `new`, with `#define NAME VALUE` injected into both stages.
+    pub fn with_defines(ctx: &mut GameContext, vs: &str, fs: &str, defines: &[(&str, &str)], init: I)
+                        -> Result<ShaderProgram<I>, ShaderError> {
+        let (pso, vs_source, fs_source) = build(&mut ctx.gfx.factory, &ctx.assets, vs, fs, defines, init.clone())?;
+        Ok(ShaderProgram {
+            vertex: Stage::new(vs, &vs_source),
+            fragment: Stage::new(fs, &fs_source),
+            defines: defines.iter().map(|&(n, v)| (n.to_string(), v.to_string())).collect(),
+            init,
+            pso
+        })
+    }
+
+    pub fn pso(&self) -> &PipelineState<Resources, I::Meta> {
+        &self.pso
+    }
+
+    /// Rebuild the pipeline if a source file changed since the last build. Returns true if the pipeline was replaced.
+    pub fn update(&mut self, ctx: &mut GameContext) -> bool {
+        if !self.vertex.changed() && !self.fragment.changed() {
+            return false;
+        }
+        // Remember the new times even if the build fails, so a broken file is reported once rather than every frame
+        self.vertex.touch();
+        self.fragment.touch();
+
+        let defines: Vec<(&str, &str)> = self.defines.iter().map(|&(ref n, ref v)| (n.as_str(), v.as_str())).collect();
+        match build(&mut ctx.gfx.factory, &ctx.assets, &self.vertex.name, &self.fragment.name, &defines, self.init.clone()) {
+            Ok((pso, vs_source, fs_source)) => {
+                println!("[shader] reloaded {} + {}", self.vertex.name, self.fragment.name);
+                // The includes may have changed too
+                self.vertex = Stage::new(&self.vertex.name, &vs_source);
+                self.fragment = Stage::new(&self.fragment.name, &fs_source);
+                self.pso = pso;
+                true
+            },
+            Err(e) => {
+                eprintln!("[shader] failed to reload {} + {}, keeping the previous pipeline:\n{}",
+                          self.vertex.name, self.fragment.name, e);
+                false
+            }
+        }
+    }
+
+}
+
+fn build<I: PipelineInit>(factory: &mut Factory, assets: &AssetManager, vs: &str, fs: &str, defines: &[(&str, &str)], init: I)
+                          -> Result<(PipelineState<Resources, I::Meta>, ShaderSource, ShaderSource), ShaderError> {
+    let vs_source = assets.load_shader(vs, defines)?;
+    let fs_source = assets.load_shader(fs, defines)?;
+
+    let pso = factory.create_pipeline_simple(vs_source.code().as_bytes(), fs_source.code().as_bytes(), init)
+        .map_err(|e| match e {
+            PipelineStateError::Program(ProgramError::Vertex(CreateShaderError::CompilationFailed(log))) =>
+                ShaderError::Pipeline(annotate_log(&vs_source, &log)),
+            PipelineStateError::Program(ProgramError::Pixel(CreateShaderError::CompilationFailed(log))) =>
+                ShaderError::Pipeline(annotate_log(&fs_source, &log)),
+            other => ShaderError::Pipeline(format!("{} + {}: {:?}", vs, fs, other))
+        })?;
+    Ok((pso, vs_source, fs_source))
+}
+
+/// Prefix each line of a driver compile log with the "file:line:" it refers to in the original sources.
+/// Understands the usual "0:12(5): error ..." (Mesa), "0(12) : error ..." (NVIDIA) and
+/// "ERROR: 0:12: ..." (AMD, Intel) forms.
+pub fn annotate_log(source: &ShaderSource, log: &str) -> String {
+    let main = source.location(1).map(|(file, _)| file).unwrap_or("");
+    log.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| match parse_log_line(l).and_then(|line| source.location(line)) {
+            Some((file, line)) => format!("{}:{}: {}", file, line, l.trim()),
+            None => format!("{}: {}", main, l.trim())
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Source line number referred to by a compile log line.
+fn parse_log_line(l: &str) -> Option<u32> {
+    let l = l.trim();
+    let l = if l.starts_with("ERROR: ") || l.starts_with("WARNING: ") {
+        &l[l.find(' ').unwrap() + 1..]
+    } else {
+        l
+    };
+    // Skip the source string index
+    let digits = l.find(|c: char| !c.is_digit(10))?;
+    if digits == 0 {
+        return None;
+    }
+    let rest = &l[digits..];
+    let rest = if rest.starts_with(':') || rest.starts_with('(') { &rest[1..] } else { return None };
+    let end = rest.find(|c: char| !c.is_digit(10))?;
+    rest[..end].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use resource::tests::TempRoot;
+
+    /// main.frag, whose line 3 in the preprocessed code is line 2 of common.glsl
+    fn source(name: &str) -> ShaderSource {
+        let root = TempRoot::with_files(&format!("shader-{}", name), &[
+            ("main.frag", "#version 330 core\n#include \"common.glsl\"\nvoid main() {}\n"),
+            ("common.glsl", "// common\nfloat x = y;\n")
+        ]);
+        root.assets().load_shader("main.frag", &[]).unwrap()
+    }
+
+    #[test]
+    fn annotate_mesa_log() {
+        assert_eq!(annotate_log(&source("mesa"), "0:3(11): error: `y' undeclared\n"),
+                   "common.glsl:2: 0:3(11): error: `y' undeclared");
+    }
+
+    #[test]
+    fn annotate_nvidia_log() {
+        assert_eq!(annotate_log(&source("nvidia"), "0(3) : error C1008: undefined variable \"y\""),
+                   "common.glsl:2: 0(3) : error C1008: undefined variable \"y\"");
+    }
+
+    #[test]
+    fn annotate_amd_log() {
+        assert_eq!(annotate_log(&source("amd"), "ERROR: 0:4: 'main' : function already has a body\nERROR: 1 compilation errors."),
+                   "main.frag:3: ERROR: 0:4: 'main' : function already has a body\nmain.frag: ERROR: 1 compilation errors.");
+    }
+}
@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+/// Longest frame the fixed-timestep accumulator will accept, so that a long stall
+/// (e.g. dragging the window) does not trigger hundreds of catch-up steps.
+const MAX_FRAME_TIME: f32 = 0.25;
+
+/// Timing of the current frame, available through `GameContext::time`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameTime {
+    /// Seconds since the previous frame. Inside `Game::fixed_update` this is the fixed step instead.
+    pub delta: f32,
+    /// Seconds since the loop started.
+    pub elapsed: f32,
+    /// Index of the current frame, starting from 0.
+    pub frame: u64,
+    /// How far the current frame is between the last and the next fixed step, in [0, 1).
+    /// Use it to interpolate rendered state. Always 0 without a fixed timestep.
+    pub alpha: f32
+}
+
+/// Drives `FrameTime` for `run`. It is given frame durations rather than reading the clock,
+/// so it can be driven by synthetic timings.
+pub(crate) struct FrameTimer {
+    /// Frames begun so far
+    frames: u64,
+    elapsed: Duration,
+    accumulator: f32,
+    fixed_step: Option<f32>,
+    min_frame_time: Option<Duration>
+}
+
+impl FrameTimer {
+
+    pub fn new(fixed_step: Option<f32>, max_fps: Option<u32>) -> FrameTimer {
+        FrameTimer {
+            frames: 0,
+            elapsed: Duration::new(0, 0),
+            accumulator: 0.0,
+            fixed_step,
+            min_frame_time: max_fps.map(|fps| Duration::new(0, 1_000_000_000 / fps.max(1)))
+        }
+    }
+
+    pub fn fixed_step(&self) -> Option<f32> {
+        self.fixed_step
+    }
+
+    /// Start a new frame, `delta` after the start of the previous one, and update `time`.
+    /// `delta` is ignored for the first frame, which has nothing before it.
+    pub fn begin_frame(&mut self, delta: Duration, time: &mut FrameTime) {
+        let delta = if self.frames == 0 { Duration::new(0, 0) } else { delta };
+        time.frame = self.frames;
+        self.frames += 1;
+        self.elapsed += delta;
+
+        time.delta = seconds(delta);
+        time.elapsed = seconds(self.elapsed);
+        if self.fixed_step.is_some() {
+            self.accumulator += time.delta.min(MAX_FRAME_TIME);
+        }
+        time.alpha = self.alpha();
+    }
+
+    /// Take one fixed step out of the accumulator if there is enough time left.
+    pub fn consume_step(&mut self) -> bool {
+        match self.fixed_step {
+            Some(step) if self.accumulator >= step => {
+                self.accumulator -= step;
+                true
+            },
+            _ => false
+        }
+    }
+
+    pub fn alpha(&self) -> f32 {
+        match self.fixed_step {
+            Some(step) => (self.accumulator / step).min(1.0),
+            None => 0.0
+        }
+    }
+
+    /// How long to sleep after a frame that took `spent` so far, to respect the frame cap.
+    pub fn frame_wait(&self, spent: Duration) -> Option<Duration> {
+        match self.min_frame_time {
+            Some(min) if spent < min => Some(min - spent),
+            _ => None
+        }
+    }
+
+}
+
+fn seconds(d: Duration) -> f32 {
+    d.as_secs() as f32 + d.subsec_nanos() as f32 * 1e-9
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Steps of 1/16 s and multiples of it are exact in f32, so the counts below are not at the mercy of rounding
+    const STEP: f32 = 0.0625;
+
+    fn millis(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    /// Begin a frame and count the fixed steps it runs
+    fn frame(timer: &mut FrameTimer, time: &mut FrameTime, delta: Duration) -> u32 {
+        timer.begin_frame(delta, time);
+        let mut steps = 0;
+        while timer.consume_step() {
+            steps += 1;
+        }
+        steps
+    }
+
+    #[test]
+    fn fixed_steps_per_frame() {
+        let mut timer = FrameTimer::new(Some(STEP), None);
+        let mut time = FrameTime::default();
+        assert_eq!(frame(&mut timer, &mut time, millis(500)), 0);
+        assert_eq!(frame(&mut timer, &mut time, Duration::new(0, 187_500_000)), 3);
+        // Half a step is carried over to the next frame
+        assert_eq!(frame(&mut timer, &mut time, Duration::new(0, 31_250_000)), 0);
+        assert_eq!(frame(&mut timer, &mut time, Duration::new(0, 31_250_000)), 1);
+    }
+
+    #[test]
+    fn alpha_is_leftover_over_step() {
+        let mut timer = FrameTimer::new(Some(STEP), None);
+        let mut time = FrameTime::default();
+        frame(&mut timer, &mut time, millis(0));
+        for &nanos in &[31_250_000, 46_875_000, 78_125_000, 15_625_000, 62_500_000] {
+            frame(&mut timer, &mut time, Duration::new(0, nanos));
+            let alpha = timer.alpha();
+            assert!(alpha >= 0.0 && alpha < 1.0, "alpha {}", alpha);
+            assert_eq!(alpha, timer.accumulator / STEP);
+        }
+        // 1/32 + 3/64 + 5/64 + 1/64 + 1/16 s = 15/64 s, 3 steps and 3/64 s left
+        assert_eq!(timer.alpha(), 0.75);
+    }
+
+    #[test]
+    fn long_frames_are_clamped() {
+        let mut timer = FrameTimer::new(Some(STEP), None);
+        let mut time = FrameTime::default();
+        frame(&mut timer, &mut time, millis(0));
+        assert_eq!(frame(&mut timer, &mut time, Duration::from_secs(5)), (MAX_FRAME_TIME / STEP) as u32);
+        // Only the accumulator is clamped, not the reported time
+        assert_eq!(time.delta, 5.0);
+        assert_eq!(time.elapsed, 5.0);
+    }
+
+    #[test]
+    fn frame_and_elapsed_are_monotonic() {
+        let mut timer = FrameTimer::new(None, None);
+        let mut time = FrameTime::default();
+        let mut last_elapsed = 0.0;
+        for (i, &ms) in [7, 0, 16, 3, 40].iter().enumerate() {
+            timer.begin_frame(millis(ms), &mut time);
+            assert_eq!(time.frame, i as u64);
+            assert!(time.elapsed >= last_elapsed);
+            assert_eq!(time.alpha, 0.0);
+            last_elapsed = time.elapsed;
+        }
+        // The first delta is ignored
+        assert!((time.elapsed - 0.059).abs() < 1e-6);
+    }
+
+    #[test]
+    fn frame_cap() {
+        let timer = FrameTimer::new(None, Some(100));
+        assert_eq!(timer.frame_wait(millis(4)), Some(millis(6)));
+        assert_eq!(timer.frame_wait(millis(12)), None);
+        assert_eq!(FrameTimer::new(None, None).frame_wait(millis(4)), None);
+    }
+}
@@ -0,0 +1,102 @@
+//! 统计每个任务产生的堆分配次数，对比 Task 的内联存储和原来的 `Box<dyn FnOnce()>`。
+//!
+//!     cargo run --release --example alloc_bench
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use thread_pool::{SegQueue, Task, ThreadPool};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const TASKS: usize = 1_000_000;
+
+/// 执行 f，返回 (每个任务的平均分配次数, 耗时)
+fn measure<F: FnOnce()>(f: F) -> (f64, f64) {
+    let start_allocs = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    f();
+    let elapsed = start.elapsed().as_secs_f64();
+    let allocs = ALLOCATIONS.load(Ordering::Relaxed) - start_allocs;
+    (allocs as f64 / TASKS as f64, elapsed)
+}
+
+fn report(name: &str, (allocs, secs): (f64, f64)) {
+    println!("{:<40} {:>8.3} allocs/task {:>10.1} ns/task", name, allocs, secs * 1e9 / TASKS as f64);
+}
+
+fn main() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let small = {
+        let counter = counter.clone();
+        move || { counter.fetch_add(1, Ordering::Relaxed); }
+    };
+    let large = {
+        let counter = counter.clone();
+        let payload = [1u8; 128];
+        move || { counter.fetch_add(payload[0] as usize, Ordering::Relaxed); }
+    };
+    fn is_inline<F: FnOnce() + Send + 'static>(_: &F) -> bool {
+        Task::is_inline::<F>()
+    }
+    println!("small closure inline: {}, large closure inline: {}", is_inline(&small), is_inline(&large));
+    println!();
+
+    report("queue: Box<dyn FnOnce()> (before)", measure(|| {
+        let q: SegQueue<Box<dyn FnOnce() + Send>> = SegQueue::new();
+        for _ in 0..TASKS {
+            q.push(Box::new(small.clone()));
+        }
+        while let Result::Ok(task) = q.pop() {
+            task();
+        }
+    }));
+
+    report("queue: Task, small closure", measure(|| {
+        let q: SegQueue<Task> = SegQueue::new();
+        for _ in 0..TASKS {
+            q.push(Task::new(small.clone()));
+        }
+        while let Result::Ok(task) = q.pop() {
+            task.run();
+        }
+    }));
+
+    report("queue: Task, large closure (boxed)", measure(|| {
+        let q: SegQueue<Task> = SegQueue::new();
+        for _ in 0..TASKS {
+            q.push(Task::new(large.clone()));
+        }
+        while let Result::Ok(task) = q.pop() {
+            task.run();
+        }
+    }));
+
+    report("ThreadPool(4) + SegQueue, small closure", measure(|| {
+        let pool = ThreadPool::builder(4).queue(SegQueue::new()).build();
+        for _ in 0..TASKS {
+            pool.queue_task(small.clone());
+        }
+        pool.join();
+    }));
+
+    assert_eq!(counter.load(Ordering::Relaxed), 4 * TASKS);
+}
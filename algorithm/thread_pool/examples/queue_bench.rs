@@ -0,0 +1,300 @@
+//! 吞吐量和排队延迟的基准测试：RingBuffer 对比 `Mutex<VecDeque>`，以及以 RingBuffer 为队列的 ThreadPool。
+//!
+//!     cargo run --release --example queue_bench -- --producers 2 --consumers 4 --capacity 1024 --cost 100 --format json --output bench.json
+//!
+//! 排队延迟是任务从入队到被消费者（工作线程）取出的时间。每一组结果写成一行 CSV 或一个 JSON 对象，
+//! 默认输出到 stdout，可读的汇总输出到 stderr。
+
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::process;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use thread_pool::{OverflowPolicy, Queue, RingBuffer, ThreadPool};
+
+/// 作为对照的最简单的有界队列
+struct MutexQueue<T> {
+    inner: Mutex<VecDeque<T>>,
+    capacity: usize
+}
+
+impl<T> MutexQueue<T> {
+
+    fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity
+        }
+    }
+
+}
+
+impl<T> Queue<T> for MutexQueue<T> where T: Send {
+
+    fn push(&self, val: T) -> Result<(), T> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.len() >= self.capacity {
+            return Result::Err(val);
+        }
+        inner.push_back(val);
+        Result::Ok(())
+    }
+
+    fn pop(&self) -> Result<T, ()> {
+        self.inner.lock().unwrap().pop_front().ok_or(())
+    }
+
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Csv,
+    Json
+}
+
+struct Config {
+    producers: usize,
+    consumers: usize,
+    capacity: usize,
+    /// 每个生产者提交的任务数
+    tasks: usize,
+    /// 每个任务空转的时间
+    cost: Duration,
+    format: Format,
+    output: Option<String>
+}
+
+const USAGE: &str = "usage: queue_bench [--producers N] [--consumers N] [--capacity N] [--tasks N] [--cost NANOS] [--format csv|json] [--output PATH]";
+
+impl Config {
+
+    fn parse<I>(mut args: I) -> Result<Self, String> where I: Iterator<Item = String> {
+        let mut config = Config {
+            producers: 2,
+            consumers: 2,
+            capacity: 1024,
+            tasks: 200_000,
+            cost: Duration::from_nanos(0),
+            format: Format::Csv,
+            output: None
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+            let number = |s: String| s.parse::<usize>().map_err(|_| format!("invalid number: {}", s));
+            match arg.as_str() {
+                "--producers" => config.producers = number(value()?)?,
+                "--consumers" => config.consumers = number(value()?)?,
+                "--capacity" => config.capacity = number(value()?)?,
+                "--tasks" => config.tasks = number(value()?)?,
+                "--cost" => config.cost = Duration::from_nanos(number(value()?)? as u64),
+                "--format" => config.format = match value()?.as_str() {
+                    "csv" => Format::Csv,
+                    "json" => Format::Json,
+                    other => return Result::Err(format!("unknown format: {}", other))
+                },
+                "--output" => config.output = Some(value()?),
+                "--help" | "-h" => return Result::Err(USAGE.to_string()),
+                other => return Result::Err(format!("unknown argument: {}\n{}", other, USAGE))
+            }
+        }
+        if config.producers == 0 || config.consumers == 0 || config.capacity == 0 {
+            return Result::Err("producers, consumers and capacity must be positive".to_string());
+        }
+        Result::Ok(config)
+    }
+
+}
+
+struct BenchResult {
+    name: &'static str,
+    seconds: f64,
+    /// 每个任务的排队延迟（纳秒），已排序
+    latencies: Vec<u64>
+}
+
+impl BenchResult {
+
+    fn new(name: &'static str, seconds: f64, mut latencies: Vec<u64>) -> Self {
+        latencies.sort_unstable();
+        Self { name, seconds, latencies }
+    }
+
+    fn throughput(&self) -> f64 {
+        self.latencies.len() as f64 / self.seconds
+    }
+
+    fn percentile(&self, p: f64) -> u64 {
+        if self.latencies.is_empty() {
+            return 0;
+        }
+        let rank = (p * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+}
+
+fn spin(cost: Duration) {
+    if cost.as_nanos() == 0 {
+        return;
+    }
+    let start = Instant::now();
+    while start.elapsed() < cost {
+        std::hint::spin_loop();
+    }
+}
+
+fn nanos_since(start: Instant) -> u64 {
+    start.elapsed().as_nanos() as u64
+}
+
+/// 生产者把入队时刻放进队列，消费者取出后记下排队延迟
+fn bench_queue<Q>(name: &'static str, queue: Q, config: &Config) -> BenchResult where Q: Queue<u64> {
+    let total = config.producers * config.tasks;
+    let consumed = AtomicUsize::new(0);
+    let start = Instant::now();
+
+    let latencies = thread::scope(|s| {
+        for _ in 0..config.producers {
+            s.spawn(|| {
+                for _ in 0..config.tasks {
+                    let mut val = nanos_since(start);
+                    while let Result::Err(v) = queue.push(val) {
+                        val = v;
+                        thread::yield_now();
+                    }
+                }
+            });
+        }
+
+        let consumers: Vec<_> = (0..config.consumers).map(|_| s.spawn(|| {
+            let mut latencies = vec![];
+            while consumed.load(Ordering::Relaxed) < total {
+                match queue.pop() {
+                    Result::Ok(enqueued) => {
+                        latencies.push(nanos_since(start).saturating_sub(enqueued));
+                        consumed.fetch_add(1, Ordering::Relaxed);
+                        spin(config.cost);
+                    },
+                    Result::Err(_) => thread::yield_now()
+                }
+            }
+            latencies
+        })).collect();
+
+        consumers.into_iter().flat_map(|c| c.join().unwrap()).collect::<Vec<_>>()
+    });
+
+    BenchResult::new(name, start.elapsed().as_secs_f64(), latencies)
+}
+
+/// 同样的负载交给 ThreadPool：消费者就是工作线程，延迟记在每个工作线程自己的状态里，最后用 broadcast 收集
+fn bench_pool(config: &Config) -> BenchResult {
+    let pool = ThreadPool::builder(config.consumers)
+        .queue(RingBuffer::new(config.capacity + 1))
+        .overflow_policy(OverflowPolicy::Block)
+        .worker_init(|_| Vec::<u64>::new())
+        .build();
+    let cost = config.cost;
+    let start = Instant::now();
+
+    thread::scope(|s| {
+        for _ in 0..config.producers {
+            s.spawn(|| {
+                for _ in 0..config.tasks {
+                    let enqueued = nanos_since(start);
+                    pool.queue_task(move || {
+                        let latency = nanos_since(start).saturating_sub(enqueued);
+                        ThreadPool::with_worker_state(|v: &mut Vec<u64>| v.push(latency));
+                        spin(cost);
+                    });
+                }
+            });
+        }
+    });
+
+    let total = (config.producers * config.tasks) as u64;
+    while pool.metrics().completed < total {
+        thread::yield_now();
+    }
+    let seconds = start.elapsed().as_secs_f64();
+    let latencies = pool.broadcast(|_| ThreadPool::with_worker_state(|v: &mut Vec<u64>| std::mem::take(v)).unwrap());
+    pool.join();
+
+    BenchResult::new("thread_pool", seconds, latencies.into_iter().flatten().collect())
+}
+
+const FIELDS: [&str; 11] = ["name", "producers", "consumers", "capacity", "tasks", "cost_ns",
+    "seconds", "tasks_per_sec", "p50_ns", "p99_ns", "p999_ns"];
+
+fn row(result: &BenchResult, config: &Config) -> Vec<String> {
+    vec![
+        result.name.to_string(),
+        config.producers.to_string(),
+        config.consumers.to_string(),
+        config.capacity.to_string(),
+        result.latencies.len().to_string(),
+        config.cost.as_nanos().to_string(),
+        format!("{:.6}", result.seconds),
+        format!("{:.0}", result.throughput()),
+        result.percentile(0.50).to_string(),
+        result.percentile(0.99).to_string(),
+        result.percentile(0.999).to_string()
+    ]
+}
+
+fn render(results: &[BenchResult], config: &Config) -> String {
+    let rows = results.iter().map(|r| row(r, config));
+    match config.format {
+        Format::Csv => {
+            let mut out = FIELDS.join(",") + "\n";
+            for row in rows {
+                out += &(row.join(",") + "\n");
+            }
+            out
+        },
+        Format::Json => {
+            // name 是固定的几个标识符，其他字段都是数字，不需要转义
+            let objects: Vec<String> = rows.map(|row| {
+                let fields: Vec<String> = FIELDS.iter().zip(row.iter()).enumerate()
+                    .map(|(i, (k, v))| if i == 0 { format!("\"{}\": \"{}\"", k, v) } else { format!("\"{}\": {}", k, v) })
+                    .collect();
+                format!("  {{{}}}", fields.join(", "))
+            }).collect();
+            format!("[\n{}\n]\n", objects.join(",\n"))
+        }
+    }
+}
+
+fn main() {
+    let config = match Config::parse(env::args().skip(1)) {
+        Result::Ok(config) => config,
+        Result::Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    let results = vec![
+        bench_queue("ring_buffer", RingBuffer::new(config.capacity + 1), &config),
+        bench_queue("mutex_vec_deque", MutexQueue::new(config.capacity), &config),
+        bench_pool(&config)
+    ];
+
+    for r in &results {
+        eprintln!("{:<16} {:>12.0} tasks/s   p50 {:>8} ns   p99 {:>9} ns   p999 {:>10} ns",
+            r.name, r.throughput(), r.percentile(0.50), r.percentile(0.99), r.percentile(0.999));
+    }
+
+    let out = render(&results, &config);
+    match &config.output {
+        Some(path) => fs::write(path, out).unwrap_or_else(|e| {
+            eprintln!("failed to write {}: {}", path, e);
+            process::exit(1);
+        }),
+        None => print!("{}", out)
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// 工作线程绑定到哪些 CPU 上（仅 Linux 有效，其他平台上会打印警告并忽略）
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Affinity {
+    /// 第 i 个工作线程绑定到 `cores[i % cores.len()]` 这一个核上
+    Cores(Vec<usize>),
+    /// 第 i 个工作线程可以在 `sets[i % sets.len()]` 里的任意核上运行
+    CoreSets(Vec<Vec<usize>>),
+    /// 每个物理核一个工作线程，绑定到这个物理核的所有超线程上。拓扑从 sysfs 读取，
+    /// 工作线程数由物理核的个数决定，忽略 `ThreadPool::builder` 的参数
+    PhysicalCores
+}
+
+/// 一个逻辑 CPU 在拓扑里的位置
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CpuInfo {
+    pub cpu: usize,
+    pub core_id: usize,
+    pub package_id: usize,
+    pub node: usize
+}
+
+/// 从 sysfs 读出的 CPU 拓扑
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CpuTopology {
+    cpus: Vec<CpuInfo>
+}
+
+impl CpuTopology {
+
+    /// 读取本机的拓扑（/sys/devices/system）
+    pub fn read() -> io::Result<Self> {
+        Self::read_from(Path::new("/sys/devices/system"))
+    }
+
+    /// 从 root 下的 cpu/ 和 node/ 目录读取拓扑，root 对应 /sys/devices/system。
+    /// 只包含在线的 CPU；没有 node/ 目录（没有开启 NUMA）时所有 CPU 都属于 0 号节点
+    pub fn read_from(root: &Path) -> io::Result<Self> {
+        let cpu_dir = root.join("cpu");
+        let online = match fs::read_to_string(cpu_dir.join("online")) {
+            Result::Ok(s) => Some(parse_cpu_list(&s)?),
+            Result::Err(_) => None
+        };
+
+        let mut nodes = BTreeMap::new();
+        if let Result::Ok(entries) = fs::read_dir(root.join("node")) {
+            for entry in entries {
+                let entry = entry?;
+                let node = match numbered(&entry.file_name().to_string_lossy(), "node") {
+                    Some(node) => node,
+                    None => continue
+                };
+                for cpu in parse_cpu_list(&fs::read_to_string(entry.path().join("cpulist"))?)? {
+                    nodes.insert(cpu, node);
+                }
+            }
+        }
+
+        let mut cpus = vec![];
+        for entry in fs::read_dir(&cpu_dir)? {
+            let entry = entry?;
+            let cpu = match numbered(&entry.file_name().to_string_lossy(), "cpu") {
+                Some(cpu) => cpu,
+                None => continue
+            };
+            if online.as_ref().is_some_and(|online| !online.contains(&cpu)) {
+                continue;
+            }
+            let topology = entry.path().join("topology");
+            cpus.push(CpuInfo {
+                cpu,
+                // 缺少拓扑信息时把每个逻辑 CPU 当成单独的物理核
+                core_id: read_number(&topology.join("core_id")).unwrap_or(cpu),
+                package_id: read_number(&topology.join("physical_package_id")).unwrap_or(0),
+                node: nodes.get(&cpu).copied().unwrap_or(0)
+            });
+        }
+        if cpus.is_empty() {
+            return Result::Err(io::Error::new(io::ErrorKind::NotFound, "no online CPU found in sysfs"));
+        }
+        cpus.sort_by_key(|c| c.cpu);
+        Result::Ok(Self { cpus })
+    }
+
+    pub fn cpus(&self) -> &[CpuInfo] {
+        &self.cpus
+    }
+
+    /// 每个物理核上的逻辑 CPU，按物理核里最小的 CPU 编号排序
+    pub fn physical_cores(&self) -> Vec<Vec<usize>> {
+        let mut cores: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+        for c in &self.cpus {
+            cores.entry((c.package_id, c.core_id)).or_default().push(c.cpu);
+        }
+        let mut cores: Vec<Vec<usize>> = cores.into_values().collect();
+        cores.sort_by_key(|cpus| cpus[0]);
+        cores
+    }
+
+    /// 每个 NUMA 节点上的逻辑 CPU，按节点编号排序
+    pub fn numa_nodes(&self) -> Vec<(usize, Vec<usize>)> {
+        let mut nodes: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for c in &self.cpus {
+            nodes.entry(c.node).or_default().push(c.cpu);
+        }
+        nodes.into_iter().collect()
+    }
+
+    fn node_of(&self, cpu: usize) -> Option<usize> {
+        self.cpus.iter().find(|c| c.cpu == cpu).map(|c| c.node)
+    }
+
+}
+
+/// "cpu12" -> Some(12)
+fn numbered(name: &str, prefix: &str) -> Option<usize> {
+    name.strip_prefix(prefix)?.parse().ok()
+}
+
+fn read_number(path: &Path) -> Option<usize> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// 解析 sysfs 里的 CPU 列表，比如 "0-3,8,10-11"
+pub fn parse_cpu_list(s: &str) -> io::Result<Vec<usize>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("invalid cpu list: {:?}", s));
+    let mut cpus = vec![];
+    for part in s.trim().split(',').filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let first: usize = first.parse().map_err(|_| invalid())?;
+                let last: usize = last.parse().map_err(|_| invalid())?;
+                if first > last {
+                    return Result::Err(invalid());
+                }
+                cpus.extend(first..=last);
+            },
+            None => cpus.push(part.parse().map_err(|_| invalid())?)
+        }
+    }
+    Result::Ok(cpus)
+}
+
+/// 工作线程的摆放方式：绑定到哪些核、使用哪个队列
+pub(crate) struct Placement {
+    pub thread_count: usize,
+    /// 每个工作线程绑定的核，None 表示不绑定
+    pub worker_cpus: Vec<Option<Vec<usize>>>,
+    /// 每个工作线程优先使用的队列编号
+    pub worker_queue: Vec<usize>,
+    pub queue_count: usize
+}
+
+impl Placement {
+
+    /// 根据配置算出摆放方式。只有需要拓扑时才调用 topology；读取失败时打印警告，退回到不绑定/单个队列
+    pub fn plan<F>(thread_count: usize, affinity: Option<&Affinity>, numa_local_queues: bool, topology: F) -> Self
+        where F: FnOnce() -> io::Result<CpuTopology> {
+        let needs_topology = numa_local_queues || affinity == Some(&Affinity::PhysicalCores);
+        let topology = if needs_topology {
+            match topology() {
+                Result::Ok(topology) => Some(topology),
+                Result::Err(e) => {
+                    eprintln!("[affinity] failed to read CPU topology, workers will not be pinned: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let (thread_count, mut worker_cpus) = match (affinity, &topology) {
+            (Some(Affinity::Cores(cores)), _) if !cores.is_empty() =>
+                (thread_count, (0..thread_count).map(|i| Some(vec![cores[i % cores.len()]])).collect()),
+            (Some(Affinity::CoreSets(sets)), _) if !sets.is_empty() =>
+                (thread_count, (0..thread_count).map(|i| Some(sets[i % sets.len()].clone())).collect()),
+            (Some(Affinity::PhysicalCores), Some(topology)) => {
+                let cores = topology.physical_cores();
+                (cores.len(), cores.into_iter().map(Some).collect())
+            },
+            _ => (thread_count, vec![None; thread_count])
+        };
+
+        let topology = match (numa_local_queues, topology) {
+            (true, Some(topology)) => topology,
+            _ => return Self {
+                thread_count,
+                worker_cpus,
+                worker_queue: vec![0; thread_count],
+                queue_count: 1
+            }
+        };
+
+        // 每个 NUMA 节点一个队列。没有绑定的工作线程依次分配到各个节点上，并绑定到这个节点的所有核
+        let nodes = topology.numa_nodes();
+        let mut worker_queue = vec![];
+        for (i, cpus) in worker_cpus.iter_mut().enumerate() {
+            let queue = match cpus {
+                Some(cpus) => cpus.first()
+                    .and_then(|&cpu| topology.node_of(cpu))
+                    .and_then(|node| nodes.iter().position(|(n, _)| *n == node))
+                    .unwrap_or(0),
+                None => {
+                    let queue = i % nodes.len();
+                    *cpus = Some(nodes[queue].1.clone());
+                    queue
+                }
+            };
+            worker_queue.push(queue);
+        }
+        Self {
+            thread_count,
+            worker_cpus,
+            worker_queue,
+            queue_count: nodes.len()
+        }
+    }
+
+}
+
+/// 把当前线程绑定到 cpus 上
+#[cfg(target_os = "linux")]
+pub(crate) fn pin_current_thread(cpus: &[usize]) -> io::Result<()> {
+    // SAFETY: cpu_set_t 是普通的位图，全零是合法的空集合
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        for &cpu in cpus {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Result::Err(io::Error::new(io::ErrorKind::InvalidInput, format!("cpu {} is out of range", cpu)));
+            }
+            libc::CPU_SET(cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Result::Err(io::Error::last_os_error());
+        }
+    }
+    Result::Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn pin_current_thread(_cpus: &[usize]) -> io::Result<()> {
+    Result::Err(io::Error::new(io::ErrorKind::Other, "CPU affinity is only supported on Linux"))
+}
+
+/// 当前线程允许运行的 CPU
+#[cfg(target_os = "linux")]
+pub fn current_thread_affinity() -> io::Result<Vec<usize>> {
+    // SAFETY: 同上
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return Result::Err(io::Error::last_os_error());
+        }
+        Result::Ok((0..libc::CPU_SETSIZE as usize).filter(|&cpu| libc::CPU_ISSET(cpu, &set)).collect())
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use crate::task::Task;
+
+/// 每个工作线程自己的任务队列，放 broadcast 发来的任务。工作线程每次取任务前先看这里，所以不会被普通任务饿死
+pub(crate) struct Mailbox {
+    inner: Mutex<MailboxInner>
+}
+
+struct MailboxInner {
+    tasks: VecDeque<Task>,
+    /// 工作线程已经退出，不再接收任务
+    closed: bool
+}
+
+impl Mailbox {
+
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(MailboxInner {
+                tasks: VecDeque::new(),
+                closed: false
+            })
+        }
+    }
+
+    /// 工作线程已经退出时把任务还回来
+    pub fn send(&self, task: Task) -> Result<(), Task> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.closed {
+            return Result::Err(task);
+        }
+        inner.tasks.push_back(task);
+        Result::Ok(())
+    }
+
+    pub fn pop(&self) -> Option<Task> {
+        self.inner.lock().unwrap().tasks.pop_front()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().unwrap().tasks.is_empty()
+    }
+
+    /// 工作线程退出时调用，丢弃还没执行的任务
+    pub fn close(&self) {
+        let tasks = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.closed = true;
+            mem::take(&mut inner.tasks)
+        };
+        // 在锁外析构，任务里的 Reply 析构时会去通知 Collector
+        drop(tasks);
+    }
+
+}
+
+/// 收集一次 broadcast 在各个工作线程上的结果
+pub(crate) struct Collector<R> {
+    state: Mutex<CollectorState<R>>,
+    all_done: Condvar
+}
+
+struct CollectorState<R> {
+    /// 按工作线程编号排列；None 表示那个工作线程没有执行（已经退出了）
+    results: Vec<Option<thread::Result<R>>>,
+    pending: usize
+}
+
+impl<R> Collector<R> {
+
+    pub fn new(worker_count: usize) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(CollectorState {
+                results: (0..worker_count).map(|_| None).collect(),
+                pending: worker_count
+            }),
+            all_done: Condvar::new()
+        })
+    }
+
+    /// 等待所有 Reply 都被发送或丢弃，返回执行了的工作线程的结果
+    pub fn wait(&self) -> Vec<thread::Result<R>> {
+        let mut state = self.state.lock().unwrap();
+        while state.pending > 0 {
+            state = self.all_done.wait(state).unwrap();
+        }
+        state.results.drain(..).flatten().collect()
+    }
+
+}
+
+/// 某个工作线程的结果要送回 Collector 的位置。没有 send 就被丢弃（任务没执行）时也会计入完成
+pub(crate) struct Reply<R> {
+    collector: Arc<Collector<R>>,
+    worker: usize
+}
+
+impl<R> Reply<R> {
+
+    pub fn new(collector: Arc<Collector<R>>, worker: usize) -> Self {
+        Self { collector, worker }
+    }
+
+    pub fn send(self, result: thread::Result<R>) {
+        self.collector.state.lock().unwrap().results[self.worker] = Some(result);
+    }
+
+}
+
+impl<R> Drop for Reply<R> {
+
+    fn drop(&mut self) {
+        let mut state = self.collector.state.lock().unwrap();
+        state.pending -= 1;
+        if state.pending == 0 {
+            self.collector.all_done.notify_all();
+        }
+    }
+
+}
@@ -0,0 +1,440 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{self, AtomicUsize, Ordering};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use crate::ring_buffer::RingBuffer;
+
+/// 基于 RingBuffer 的有界 MPMC channel。
+///
+/// 收发本身走无锁的 RingBuffer；只有在队列满/空需要阻塞等待时，才会把当前线程登记到对应的等待列表里然后 park。
+/// `size` 的含义和 `RingBuffer::new` 一样，最多同时缓存 size - 1 个值。
+pub fn channel<T>(size: usize) -> (Sender<T>, Receiver<T>) where T: Send {
+    let shared = Arc::new(Shared {
+        buffer: RingBuffer::new(size),
+        senders: AtomicUsize::new(1),
+        receivers: AtomicUsize::new(1),
+        send_waiters: Waiters::new(),
+        recv_waiters: Waiters::new()
+    });
+    (Sender { shared: shared.clone() }, Receiver { shared })
+}
+
+struct Shared<T> where T: Send {
+    buffer: RingBuffer<T>,
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+    /// 因为队列满而阻塞的 send
+    send_waiters: Waiters,
+    /// 因为队列空而阻塞的 recv（包括 select）
+    recv_waiters: Waiters
+}
+
+/// 等待中的线程列表。
+///
+/// 等待方先登记、再检查一次条件、最后 park；通知方先改变状态、再检查有没有人在等。
+/// 两边都隔着一个 SeqCst fence，保证至少有一方能看到对方，不会丢失唤醒。
+struct Waiters {
+    threads: Mutex<Vec<Thread>>,
+    count: AtomicUsize
+}
+
+impl Waiters {
+
+    fn new() -> Self {
+        Self {
+            threads: Mutex::new(Vec::new()),
+            count: AtomicUsize::new(0)
+        }
+    }
+
+    fn register(&self) {
+        let mut threads = self.threads.lock().unwrap();
+        threads.push(thread::current());
+        self.count.store(threads.len(), Ordering::SeqCst);
+        drop(threads);
+        atomic::fence(Ordering::SeqCst);
+    }
+
+    fn unregister(&self) {
+        let id = thread::current().id();
+        let mut threads = self.threads.lock().unwrap();
+        threads.retain(|t| t.id() != id);
+        self.count.store(threads.len(), Ordering::SeqCst);
+    }
+
+    fn notify_all(&self) {
+        atomic::fence(Ordering::SeqCst);
+        if self.count.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let mut threads = self.threads.lock().unwrap();
+        for t in threads.drain(..) {
+            t.unpark();
+        }
+        self.count.store(0, Ordering::SeqCst);
+    }
+
+}
+
+/// park 到 deadline 为止；返回 false 表示已经超时
+fn park_until(deadline: Option<Instant>) -> bool {
+    match deadline {
+        Some(deadline) => {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            thread::park_timeout(deadline - now);
+        },
+        None => thread::park()
+    }
+    true
+}
+
+pub struct Sender<T> where T: Send {
+    shared: Arc<Shared<T>>
+}
+
+pub struct Receiver<T> where T: Send {
+    shared: Arc<Shared<T>>
+}
+
+/// 所有 Receiver 都已经 drop，值原样还回来
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SendError<T>(pub T);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrySendError<T> {
+    Full(T),
+    Disconnected(T)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SendTimeoutError<T> {
+    Timeout(T),
+    Disconnected(T)
+}
+
+/// 所有 Sender 都已经 drop，并且队列里没有剩下的值
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RecvError;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecvTimeoutError {
+    Timeout,
+    Disconnected
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "sending on a disconnected channel")
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "sending on a full channel"),
+            TrySendError::Disconnected(_) => write!(f, "sending on a disconnected channel")
+        }
+    }
+}
+
+impl<T> fmt::Display for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(_) => write!(f, "timed out waiting on send operation"),
+            SendTimeoutError::Disconnected(_) => write!(f, "sending on a disconnected channel")
+        }
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "receiving on a disconnected channel")
+    }
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "receiving on an empty channel"),
+            TryRecvError::Disconnected => write!(f, "receiving on a disconnected channel")
+        }
+    }
+}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout => write!(f, "timed out waiting on receive operation"),
+            RecvTimeoutError::Disconnected => write!(f, "receiving on a disconnected channel")
+        }
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for SendError<T> {}
+impl<T: fmt::Debug> std::error::Error for TrySendError<T> {}
+impl<T: fmt::Debug> std::error::Error for SendTimeoutError<T> {}
+impl std::error::Error for RecvError {}
+impl std::error::Error for TryRecvError {}
+impl std::error::Error for RecvTimeoutError {}
+
+impl<T> Sender<T> where T: Send {
+
+    pub fn try_send(&self, val: T) -> Result<(), TrySendError<T>> {
+        if self.shared.receivers.load(Ordering::SeqCst) == 0 {
+            return Result::Err(TrySendError::Disconnected(val));
+        }
+        match self.shared.buffer.push(val) {
+            Result::Ok(_) => {
+                self.shared.recv_waiters.notify_all();
+                Result::Ok(())
+            },
+            Result::Err(val) => Result::Err(TrySendError::Full(val))
+        }
+    }
+
+    /// 队列满时阻塞，直到有空位或者所有 Receiver 都被 drop
+    pub fn send(&self, val: T) -> Result<(), SendError<T>> {
+        match self.send_until(val, None) {
+            Result::Ok(_) => Result::Ok(()),
+            Result::Err(SendTimeoutError::Disconnected(val)) => Result::Err(SendError(val)),
+            Result::Err(SendTimeoutError::Timeout(_)) => unreachable!()
+        }
+    }
+
+    pub fn send_timeout(&self, val: T, timeout: Duration) -> Result<(), SendTimeoutError<T>> {
+        self.send_until(val, Some(Instant::now() + timeout))
+    }
+
+    fn send_until(&self, mut val: T, deadline: Option<Instant>) -> Result<(), SendTimeoutError<T>> {
+        loop {
+            val = match self.try_send(val) {
+                Result::Ok(_) => return Result::Ok(()),
+                Result::Err(TrySendError::Disconnected(v)) => return Result::Err(SendTimeoutError::Disconnected(v)),
+                Result::Err(TrySendError::Full(v)) => v
+            };
+
+            self.shared.send_waiters.register();
+            // 登记之后再试一次，避免在登记之前 Receiver 刚好腾出了位置
+            let result = self.try_send(val);
+            let timed_out = matches!(result, Result::Err(TrySendError::Full(_))) && !park_until(deadline);
+            self.shared.send_waiters.unregister();
+
+            val = match result {
+                Result::Ok(_) => return Result::Ok(()),
+                Result::Err(TrySendError::Disconnected(v)) => return Result::Err(SendTimeoutError::Disconnected(v)),
+                Result::Err(TrySendError::Full(v)) if timed_out => return Result::Err(SendTimeoutError::Timeout(v)),
+                Result::Err(TrySendError::Full(v)) => v
+            };
+        }
+    }
+
+}
+
+impl<T> Clone for Sender<T> where T: Send {
+
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::SeqCst);
+        Self { shared: self.shared.clone() }
+    }
+
+}
+
+impl<T> Drop for Sender<T> where T: Send {
+
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // 最后一个 Sender，叫醒所有在等的 Receiver 让它们看到断开
+            self.shared.recv_waiters.notify_all();
+        }
+    }
+
+}
+
+impl<T> Receiver<T> where T: Send {
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        match self.shared.buffer.pop() {
+            Result::Ok(x) => {
+                self.shared.send_waiters.notify_all();
+                Result::Ok(x)
+            },
+            Result::Err(_) => {
+                if self.shared.senders.load(Ordering::SeqCst) == 0 {
+                    // 断开之前最后发出的值可能刚刚写进来，再取一次
+                    match self.shared.buffer.pop() {
+                        Result::Ok(x) => Result::Ok(x),
+                        Result::Err(_) => Result::Err(TryRecvError::Disconnected)
+                    }
+                } else {
+                    Result::Err(TryRecvError::Empty)
+                }
+            }
+        }
+    }
+
+    /// 队列空时阻塞，直到收到值或者所有 Sender 都被 drop
+    pub fn recv(&self) -> Result<T, RecvError> {
+        match self.recv_until(None) {
+            Result::Ok(x) => Result::Ok(x),
+            Result::Err(_) => Result::Err(RecvError)
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.recv_until(Some(Instant::now() + timeout))
+    }
+
+    fn recv_until(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        select_until(&[self], deadline).map(|(_, x)| x)
+    }
+
+    /// 阻塞地迭代收到的值，直到 channel 断开
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { rx: self }
+    }
+
+    /// 只迭代当前已经在队列里的值，不阻塞
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        TryIter { rx: self }
+    }
+
+}
+
+impl<T> Clone for Receiver<T> where T: Send {
+
+    fn clone(&self) -> Self {
+        self.shared.receivers.fetch_add(1, Ordering::SeqCst);
+        Self { shared: self.shared.clone() }
+    }
+
+}
+
+impl<T> Drop for Receiver<T> where T: Send {
+
+    fn drop(&mut self) {
+        if self.shared.receivers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.shared.send_waiters.notify_all();
+        }
+    }
+
+}
+
+pub struct Iter<'a, T> where T: Send {
+    rx: &'a Receiver<T>
+}
+
+impl<'a, T> Iterator for Iter<'a, T> where T: Send {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+pub struct TryIter<'a, T> where T: Send {
+    rx: &'a Receiver<T>
+}
+
+impl<'a, T> Iterator for TryIter<'a, T> where T: Send {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.try_recv().ok()
+    }
+}
+
+pub struct IntoIter<T> where T: Send {
+    rx: Receiver<T>
+}
+
+impl<T> Iterator for IntoIter<T> where T: Send {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> where T: Send {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> where T: Send {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { rx: self }
+    }
+}
+
+/// 同时等待多个 Receiver，返回第一个收到值的 Receiver 下标和值。
+/// 已经断开的 Receiver 会被跳过；全部断开时返回 `RecvError`。
+pub fn select<T>(receivers: &[&Receiver<T>]) -> Result<(usize, T), RecvError> where T: Send {
+    match select_until(receivers, None) {
+        Result::Ok(x) => Result::Ok(x),
+        Result::Err(_) => Result::Err(RecvError)
+    }
+}
+
+pub fn select_timeout<T>(receivers: &[&Receiver<T>], timeout: Duration)
+    -> Result<(usize, T), RecvTimeoutError> where T: Send {
+    select_until(receivers, Some(Instant::now() + timeout))
+}
+
+fn select_until<T>(receivers: &[&Receiver<T>], deadline: Option<Instant>)
+    -> Result<(usize, T), RecvTimeoutError> where T: Send {
+    let try_all = || {
+        let mut disconnected = 0;
+        for (i, rx) in receivers.iter().enumerate() {
+            match rx.try_recv() {
+                Result::Ok(x) => return Result::Ok((i, x)),
+                Result::Err(TryRecvError::Disconnected) => disconnected += 1,
+                Result::Err(TryRecvError::Empty) => ()
+            }
+        }
+        if disconnected == receivers.len() {
+            Result::Err(RecvTimeoutError::Disconnected)
+        } else {
+            Result::Err(RecvTimeoutError::Timeout)
+        }
+    };
+
+    loop {
+        match try_all() {
+            Result::Err(RecvTimeoutError::Timeout) => (),
+            done => return done
+        }
+
+        for rx in receivers {
+            rx.shared.recv_waiters.register();
+        }
+        // 登记之后再试一次，避免在登记之前 Sender 刚好发了值
+        let result = try_all();
+        let timed_out = matches!(result, Result::Err(RecvTimeoutError::Timeout)) && !park_until(deadline);
+        for rx in receivers {
+            rx.shared.recv_waiters.unregister();
+        }
+
+        match result {
+            Result::Err(RecvTimeoutError::Timeout) if !timed_out => (),
+            done => return done
+        }
+    }
+}
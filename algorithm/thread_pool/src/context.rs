@@ -0,0 +1,125 @@
+use std::any::{Any, TypeId};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+
+/// 每个工作线程启动时调用一次，参数是工作线程编号，返回值作为这个工作线程的状态
+pub(crate) type WorkerInit = Arc<dyn Fn(usize) -> Box<dyn Any + Send> + Send + Sync + 'static>;
+
+/// 提交任务时附带的 task-local 值，每种类型最多一个
+#[derive(Clone, Default)]
+pub(crate) struct TaskLocals(Vec<(TypeId, Arc<dyn Any + Send + Sync>)>);
+
+impl TaskLocals {
+
+    /// 插入一个值，已经有同类型的值时替换掉
+    pub fn insert<T>(&mut self, value: T) where T: Send + Sync + 'static {
+        let id = TypeId::of::<T>();
+        self.0.retain(|(x, _)| *x != id);
+        self.0.push((id, Arc::new(value)));
+    }
+
+    fn get(&self, id: TypeId) -> Option<Arc<dyn Any + Send + Sync>> {
+        self.0.iter()
+            .find(|(x, _)| *x == id)
+            .map(|(_, v)| v.clone())
+    }
+
+}
+
+impl fmt::Debug for TaskLocals {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TaskLocals({} values)", self.0.len())
+    }
+}
+
+thread_local! {
+    /// 当前线程是哪个工作线程；不是工作线程时为 None
+    static CURRENT_WORKER: Cell<Option<usize>> = const { Cell::new(None) };
+    /// worker_init 创建的工作线程状态
+    static WORKER_STATE: RefCell<Option<Box<dyn Any + Send>>> = RefCell::new(None);
+    /// 当前正在执行的任务的 task-local 值
+    static TASK_LOCALS: RefCell<TaskLocals> = RefCell::new(TaskLocals::default());
+}
+
+/// 当前线程在 ThreadPool 里的工作线程编号
+pub(crate) fn current_worker() -> Option<usize> {
+    CURRENT_WORKER.with(|w| w.get())
+}
+
+/// 以 index 号工作线程的身份执行，直到返回的 WorkerScope 被 drop 或 exit
+pub(crate) fn enter_worker(index: usize, state: Option<Box<dyn Any + Send>>) -> WorkerScope {
+    let prev_worker = CURRENT_WORKER.with(|w| w.replace(Some(index)));
+    let prev_state = WORKER_STATE.with(|s| mem::replace(&mut *s.borrow_mut(), state));
+    WorkerScope { prev_worker, prev_state }
+}
+
+pub(crate) struct WorkerScope {
+    prev_worker: Option<usize>,
+    prev_state: Option<Box<dyn Any + Send>>
+}
+
+impl WorkerScope {
+
+    /// 离开工作线程身份，把工作线程状态拿回来
+    pub fn exit(self) -> Option<Box<dyn Any + Send>> {
+        // 之前的状态在 drop 里恢复
+        WORKER_STATE.with(|s| s.borrow_mut().take())
+    }
+
+}
+
+impl Drop for WorkerScope {
+
+    fn drop(&mut self) {
+        CURRENT_WORKER.with(|w| w.set(self.prev_worker));
+        let prev_state = self.prev_state.take();
+        // 在借用之外析构被替换掉的状态，它的 drop 里也可能访问工作线程状态
+        let state = WORKER_STATE.with(|s| mem::replace(&mut *s.borrow_mut(), prev_state));
+        drop(state);
+    }
+
+}
+
+/// 对当前工作线程的状态执行 f。执行 f 期间状态被取出，所以嵌套调用会得到 None 而不是 panic
+pub(crate) fn with_worker_state<S, R, F>(f: F) -> Option<R> where S: 'static, F: FnOnce(&mut S) -> R {
+    let state = WORKER_STATE.with(|s| s.borrow_mut().take())?;
+    let mut taken = TakenState(Some(state));
+    taken.0.as_mut().unwrap().downcast_mut::<S>().map(f)
+}
+
+/// 被 with_worker_state 取出的工作线程状态，drop 时放回去，f panic 时也一样
+struct TakenState(Option<Box<dyn Any + Send>>);
+
+impl Drop for TakenState {
+
+    fn drop(&mut self) {
+        let state = self.0.take();
+        WORKER_STATE.with(|s| *s.borrow_mut() = state);
+    }
+
+}
+
+/// 把 locals 设为当前任务的 task-local 值，直到返回的 TaskScope 被 drop
+pub(crate) fn enter_task(locals: TaskLocals) -> TaskScope {
+    TaskScope(TASK_LOCALS.with(|l| mem::replace(&mut *l.borrow_mut(), locals)))
+}
+
+pub(crate) struct TaskScope(TaskLocals);
+
+impl Drop for TaskScope {
+
+    fn drop(&mut self) {
+        let prev = mem::take(&mut self.0);
+        let locals = TASK_LOCALS.with(|l| mem::replace(&mut *l.borrow_mut(), prev));
+        drop(locals);
+    }
+
+}
+
+pub(crate) fn with_task_local<T, R, F>(f: F) -> Option<R> where T: 'static, F: FnOnce(&T) -> R {
+    // 先克隆出 Arc 再调用 f，这样 f 里面再执行任务（模拟模式下的 step）也不会和这里的借用冲突
+    let value = TASK_LOCALS.with(|l| l.borrow().get(TypeId::of::<T>()))?;
+    value.downcast_ref::<T>().map(f)
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// ThreadPool 生命周期里的事件
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EventKind {
+    /// 任务放进了队列（被 `OverflowPolicy::Discard` 丢弃的任务没有这个事件）
+    TaskSubmitted,
+    TaskStarted,
+    TaskFinished,
+    /// 任务 panic 了，之后没有 TaskFinished。执行它的工作线程会随之退出
+    TaskPanicked,
+    /// 工作线程没有任务可做（队列空了、被限速或者暂停）
+    WorkerParked,
+    /// 工作线程重新开始执行任务
+    WorkerWoken,
+    /// ThreadPool 被 join 或者 drop
+    PoolShutdown
+}
+
+/// 一个事件。只在调用 EventSink 期间有效，需要保存的话自己复制
+#[derive(Clone, Copy, Debug)]
+pub struct Event<'a> {
+    pub kind: EventKind,
+    /// 从 ThreadPool 创建开始经过的时间
+    pub timestamp: Duration,
+    /// 任务编号，同一个 ThreadPool 里按提交顺序从 0 开始递增。和任务无关的事件为 None
+    pub task_id: Option<u64>,
+    /// 工作线程编号（模拟模式下是 0）。TaskSubmitted 和 PoolShutdown 为 None
+    pub worker: Option<usize>,
+    /// 提交时通过 `TaskOptions::label` 设置的标签
+    pub label: Option<&'a str>
+}
+
+/// 接收 ThreadPool 事件的地方，通过 `ThreadPoolBuilder::event_sink` 设置。
+///
+/// 会在提交任务的线程和工作线程上被并发调用，应该尽快返回。
+pub trait EventSink: Send + Sync {
+    fn event(&self, event: &Event);
+}
+
+struct RecordedEvent {
+    kind: EventKind,
+    timestamp: Duration,
+    task_id: Option<u64>,
+    worker: Option<usize>,
+    label: Option<String>
+}
+
+/// 把事件记在内存里，之后导出成 Chrome trace event 格式的 JSON，可以用 chrome://tracing 或 Perfetto 打开查看时间线
+#[derive(Default)]
+pub struct ChromeTraceSink {
+    events: Mutex<Vec<RecordedEvent>>
+}
+
+impl ChromeTraceSink {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_to(&mut w)?;
+        w.flush()
+    }
+
+    /// 每个工作线程是一条时间线，任务是上面的一段；提交任务和关闭 ThreadPool 是全局的瞬时事件
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let events = self.events.lock().unwrap();
+        let mut entries = vec![];
+
+        let workers: BTreeSet<usize> = events.iter().filter_map(|e| e.worker).collect();
+        for worker in workers {
+            entries.push(format!(
+                "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"worker #{}\"}}}}",
+                worker, worker));
+        }
+
+        for e in events.iter() {
+            let name = match (&e.label, e.task_id) {
+                (Some(label), _) => escape(label),
+                (None, Some(id)) => format!("task #{}", id),
+                (None, None) => String::new()
+            };
+            let (ph, name) = match e.kind {
+                EventKind::TaskSubmitted => ("i", format!("submit {}", name)),
+                EventKind::TaskStarted => ("B", name),
+                EventKind::TaskFinished => ("E", name),
+                EventKind::TaskPanicked => ("E", format!("{} (panicked)", name)),
+                EventKind::WorkerParked => ("i", "parked".to_string()),
+                EventKind::WorkerWoken => ("i", "woken".to_string()),
+                EventKind::PoolShutdown => ("i", "shutdown".to_string())
+            };
+            let ts = e.timestamp.as_nanos() as f64 / 1000.0;
+            let mut entry = format!("{{\"name\":\"{}\",\"ph\":\"{}\",\"ts\":{:.3},\"pid\":1", name, ph, ts);
+            match e.worker {
+                Some(worker) => entry += &format!(",\"tid\":{}", worker),
+                // 不属于任何工作线程的瞬时事件画成横跨整个进程的竖线
+                None => entry += ",\"tid\":0,\"s\":\"g\""
+            }
+            if ph == "i" && e.worker.is_some() {
+                entry += ",\"s\":\"t\"";
+            }
+            if let Some(id) = e.task_id {
+                entry += &format!(",\"args\":{{\"task_id\":{}}}", id);
+            }
+            entry += "}";
+            entries.push(entry);
+        }
+
+        writeln!(w, "{{\"traceEvents\":[")?;
+        for (i, entry) in entries.iter().enumerate() {
+            let sep = if i + 1 < entries.len() { "," } else { "" };
+            writeln!(w, "{}{}", entry, sep)?;
+        }
+        writeln!(w, "]}}")
+    }
+
+}
+
+impl EventSink for ChromeTraceSink {
+    fn event(&self, event: &Event) {
+        self.events.lock().unwrap().push(RecordedEvent {
+            kind: event.kind,
+            timestamp: event.timestamp,
+            task_id: event.task_id,
+            worker: event.worker,
+            label: event.label.map(|s| s.to_string())
+        });
+    }
+}
+
+/// JSON 字符串转义
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c)
+        }
+    }
+    out
+}
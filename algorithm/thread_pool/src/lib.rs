@@ -0,0 +1,37 @@
+// 队列的 pop 沿用 RingBuffer 最初的 `Result<T, ()>` 写法
+#![allow(clippy::result_unit_err)]
+
+pub mod affinity;
+mod broadcast;
+pub mod channel;
+mod context;
+mod events;
+mod metrics;
+mod queue;
+mod rate_limit;
+mod ring_buffer;
+mod seg_queue;
+pub mod simulation;
+mod task;
+mod task_group;
+#[cfg(unix)]
+pub mod shm_ring;
+mod tenant;
+mod thread_pool;
+mod watchdog;
+
+pub use crate::affinity::{Affinity, CpuTopology};
+pub use crate::channel::{channel, select, select_timeout, Receiver, Sender};
+pub use crate::events::{ChromeTraceSink, Event, EventKind, EventSink};
+pub use crate::metrics::MetricsSnapshot;
+pub use crate::queue::Queue;
+pub use crate::rate_limit::{Clock, MockClock, RateLimiter, SystemClock};
+pub use crate::ring_buffer::RingBuffer;
+pub use crate::seg_queue::SegQueue;
+#[cfg(unix)]
+pub use crate::shm_ring::ShmRing;
+pub use crate::task::Task;
+pub use crate::task_group::{GroupError, GroupResult, TaskGroup};
+pub use crate::tenant::{TenantMetrics, TenantOptions};
+pub use crate::thread_pool::{Job, OverflowPolicy, TaskOptions, Tenant, ThreadPool, ThreadPoolBuilder, ThreadPoolEntry};
+pub use crate::watchdog::OverrunReport;
@@ -1,230 +1,1114 @@
-use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering, AtomicBool};
-use std::cell::UnsafeCell;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread;
 
-/// 一个勉强能用的RingBuffer
-struct RingBuffer<T> where T: Sized + Send {
-    /// 使用 UnsafeCell 让我们可以在 &self 里对 Vec 进行操作。
-    /// 这样才能在正常的使用里避免多线程加锁（不然就需要Arc<RwLock<RingBuffer>>>，破坏了无锁队列的初衷。。）
-    arr: Vec<UnsafeCell<Option<T>>>,
-    head: AtomicUsize,
-    tail: AtomicUsize
-}
+use thread_pool::{channel, select, select_timeout, Affinity, ChromeTraceSink, CpuTopology, Event, EventKind, EventSink, GroupError, MockClock, OverflowPolicy, OverrunReport, RateLimiter, RingBuffer, SegQueue, Task, TaskGroup, TaskOptions, TenantMetrics, TenantOptions, ThreadPool};
+use thread_pool::channel::{RecvTimeoutError, SendTimeoutError, TryRecvError, TrySendError};
+
+fn test_queue() {
+    println!("Test queue: single case");
+    {
+        let q: RingBuffer<u32> = RingBuffer::new(8);
+        for i in 0..7 {
+            println!("Push {}", i);
+            assert_eq!(q.push(i), Result::Ok(()));
+        }
 
-impl<T> RingBuffer<T> where T: Sized + Send {
+        println!("Push 7 (out of range)");
+        assert_eq!(q.push(7), Result::Err(7));
 
-    fn new(size: usize) -> Self {
-        assert!(size > 1);
-        let mut v = Vec::new();
-        for _ in 0..size {
-            v.push(None.into());
+        for _ in 0..4 {
+            match q.pop() {
+                Result::Ok(x) => println!("Pop returns {}", x),
+                _ => panic!()
+            }
         }
-        Self {
-            arr: v,
-            head: AtomicUsize::new(0),
-            tail: AtomicUsize::new(0) 
+
+        println!("Push 5");
+        assert_eq!(q.push(5), Result::Ok( () ));
+        for _ in 0..5 {
+            match q.pop() {
+                Result::Ok(x) => println!("Pop returns {}", x),
+                _ => println!("Pop returns None")
+            }
         }
-    }
 
-    fn push(&self, val: T) -> Result<(), T> {
-        let size = self.size();
-        // tail = (tail + 1) % (size)
-        // if tail + 1 == head (with modulo): overflow
+        for i in 0..7 {
+            println!("Push {}", i + 8);
+            assert_eq!(q.push(i), Result::Ok(()));
+        }
 
-        // CAS
-        let mut last_tail = self.tail.load(Ordering::Relaxed);
-        loop {
-            let nlast = (last_tail + 1) % size;
-            // 在cas里比较队列是否满，若满直接返回
-            let head = self.head.load(Ordering::Relaxed);
-            // println!("  push head={}, tail={}", head, last_tail);
-            if (last_tail + 1 + size - head) % size == 0 {
-                return Result::Err(val);
-            }
-            match self.tail.compare_exchange(last_tail, nlast, 
-                Ordering::Release, Ordering::Relaxed) {
-                Result::Ok(_) => {
-                    break;
-                },
-                Result::Err(x) => last_tail = x
+        for _ in 0..7 {
+            match q.pop() {
+                Result::Ok(x) => println!("Pop returns {}", x),
+                _ => println!("Pop returns None")
             }
         }
+    }
+}
 
-        let slot = last_tail;
-        unsafe { // SAFETY: 多个线程不会访问同一个cell
-            *self.arr[slot].get() = Some(val)
+fn test_seg_queue() {
+    println!("Test seg queue: single case");
+    {
+        let q: SegQueue<u32> = SegQueue::new();
+        // 跨过好几个分段
+        for i in 0..100 {
+            q.push(i);
         }
-        Result::Ok(())
+        for i in 0..100 {
+            assert_eq!(q.pop(), Result::Ok(i));
+        }
+        assert_eq!(q.pop(), Result::Err(()));
+        assert!(q.is_empty());
     }
 
-    fn pop(&self) -> Result<T, ()> {
-        let size = self.size();
+    println!("Test seg queue: 4 producers, 4 consumers");
+    {
+        const PER_PRODUCER: usize = 10000;
+        let q = Arc::new(SegQueue::<usize>::new());
+        let popped = Arc::new(AtomicUsize::new(0));
+        let sum = Arc::new(AtomicUsize::new(0));
 
-        let mut last_head = self.head.load(Ordering::Relaxed);
-        loop {
-            let nlast = (last_head + 1) % size;
-            // 在cas里比较队列是否空，如果是，直接返回
-            let tail = self.tail.load(Ordering::Relaxed);
-            if last_head == tail {
-                return Result::Err(());
-            }
+        let mut handles = vec![];
+        for p in 0..4 {
+            let q = q.clone();
+            handles.push(thread::spawn(move || {
+                for i in 0..PER_PRODUCER {
+                    q.push(p * PER_PRODUCER + i);
+                }
+            }));
+        }
+        for _ in 0..4 {
+            let (q, popped, sum) = (q.clone(), popped.clone(), sum.clone());
+            handles.push(thread::spawn(move || {
+                while popped.load(Ordering::Relaxed) < 4 * PER_PRODUCER {
+                    if let Result::Ok(x) = q.pop() {
+                        sum.fetch_add(x, Ordering::Relaxed);
+                        popped.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }));
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
 
-            match self.head.compare_exchange(last_head, nlast,
-                Ordering::Acquire, Ordering::Relaxed) {
-                Result::Ok(_) => {
-                    // last_head = x;
-                    break;
-                },
-                Result::Err(x) => last_head = x,
-            }
+        let n = 4 * PER_PRODUCER;
+        assert_eq!(sum.load(Ordering::Relaxed), n * (n - 1) / 2);
+        assert!(q.is_empty());
+    }
+}
+
+fn test_channel() {
+    use std::time::Duration;
+
+    println!("Test channel: single thread");
+    {
+        let (tx, rx) = channel::<u32>(4);
+        for i in 0..3 {
+            assert_eq!(tx.try_send(i), Result::Ok(()));
         }
+        assert_eq!(tx.try_send(3), Result::Err(TrySendError::Full(3)));
+        assert_eq!(tx.send_timeout(3, Duration::from_millis(10)), Result::Err(SendTimeoutError::Timeout(3)));
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(rx.try_recv(), Result::Err(TryRecvError::Empty));
+        assert_eq!(rx.recv_timeout(Duration::from_millis(10)), Result::Err(RecvTimeoutError::Timeout));
+
+        tx.send(42).unwrap();
+        drop(tx);
+        // 断开之前发出的值仍然能收到
+        assert_eq!(rx.recv(), Result::Ok(42));
+        assert_eq!(rx.try_recv(), Result::Err(TryRecvError::Disconnected));
+        assert!(rx.recv().is_err());
+    }
+
+    println!("Test channel: receiver dropped");
+    {
+        let (tx, rx) = channel::<u32>(2);
+        tx.send(1).unwrap();
+        let handle = thread::spawn(move || {
+            // 队列满，阻塞到 Receiver 被 drop
+            tx.send(2)
+        });
+        thread::sleep(Duration::from_millis(50));
+        drop(rx);
+        assert_eq!(handle.join().unwrap().unwrap_err().0, 2);
+    }
+
+    println!("Test channel: 4 senders, 2 receivers");
+    {
+        const PER_SENDER: usize = 5000;
+        let (tx, rx) = channel::<usize>(8);
+        let mut senders = vec![];
+        for p in 0..4 {
+            let tx = tx.clone();
+            senders.push(thread::spawn(move || {
+                for i in 0..PER_SENDER {
+                    tx.send(p * PER_SENDER + i).unwrap();
+                }
+            }));
+        }
+        drop(tx);
+
+        let receivers: Vec<_> = (0..2).map(|_| {
+            let rx = rx.clone();
+            thread::spawn(move || rx.iter().sum::<usize>())
+        }).collect();
+        drop(rx);
+
+        for handle in senders {
+            handle.join().unwrap();
+        }
+        let sum: usize = receivers.into_iter().map(|h| h.join().unwrap()).sum();
+        let n = 4 * PER_SENDER;
+        assert_eq!(sum, n * (n - 1) / 2);
+    }
+
+    println!("Test channel: select");
+    {
+        let (tx1, rx1) = channel::<&str>(4);
+        let (tx2, rx2) = channel::<&str>(4);
+        assert_eq!(select_timeout(&[&rx1, &rx2], Duration::from_millis(10)), Result::Err(RecvTimeoutError::Timeout));
+
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            tx2.send("second").unwrap();
+            drop(tx2);
+            tx1
+        });
+        assert_eq!(select(&[&rx1, &rx2]), Result::Ok((1, "second")));
+        let tx1 = handle.join().unwrap();
+
+        tx1.send("first").unwrap();
+        assert_eq!(select(&[&rx1, &rx2]), Result::Ok((0, "first")));
+        drop(tx1);
+        assert!(select(&[&rx1, &rx2]).is_err());
+    }
+}
 
-        let mut elem: Option<T> = None;
-        unsafe { // SAFETY: 多个线程不会访问同一个cell
-            let ptr = self.arr[last_head].get();
-            std::ptr::swap((&mut elem) as *mut Option<T>, ptr);
+#[cfg(target_os = "linux")]
+fn test_shm_ring() {
+    use std::fs;
+    use std::io;
+    use thread_pool::ShmRing;
+    use thread_pool::shm_ring::{PopError, PushError};
+
+    /// fork 一个子进程执行 f，返回子进程 pid。子进程执行完 f 后直接 _exit，不跑父进程的析构。
+    fn fork_child<F: FnOnce() -> i32>(f: F) -> libc::pid_t {
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0, "fork failed");
+        if pid == 0 {
+            let code = f();
+            unsafe { libc::_exit(code) }
         }
+        pid
+    }
 
-        match elem {
-            Some(x) => Result::Ok(x),
-            None => Result::Err(())
+    fn wait_child(pid: libc::pid_t) {
+        let mut status = 0;
+        unsafe { libc::waitpid(pid, &mut status, 0) };
+        assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0, "child failed: {}", status);
+    }
+
+    fn message(i: usize) -> Vec<u8> {
+        format!("message #{}", i).repeat(i % 7 + 1).into_bytes()
+    }
+
+    println!("Test shm ring: single process");
+    {
+        let ring = ShmRing::anonymous(4, 16).unwrap();
+        assert_eq!(ring.pop(), Result::Err(PopError::Empty));
+        assert_eq!(ring.push(&[0u8; 17]), Result::Err(PushError::TooLarge));
+        for i in 0..4u8 {
+            assert_eq!(ring.push(&[i; 3]), Result::Ok(()));
         }
+        assert_eq!(ring.push(b"full"), Result::Err(PushError::Full));
+        for i in 0..4u8 {
+            assert_eq!(ring.pop(), Result::Ok(vec![i; 3]));
+        }
+        assert_eq!(ring.push(b""), Result::Ok(()));
+        assert_eq!(ring.pop(), Result::Ok(vec![]));
     }
 
-    fn size(&self) -> usize {
-        self.arr.len()
+    println!("Test shm ring: fork, child produces, parent consumes");
+    {
+        const COUNT: usize = 10000;
+        let ring = ShmRing::anonymous(8, 128).unwrap();
+        let pid = fork_child(|| {
+            for i in 0..COUNT {
+                while ring.push(&message(i)) == Result::Err(PushError::Full) {
+                    thread::yield_now();
+                }
+            }
+            0
+        });
+        for i in 0..COUNT {
+            let msg = loop {
+                match ring.pop() {
+                    Result::Ok(msg) => break msg,
+                    Result::Err(PopError::Empty) => thread::yield_now(),
+                    Result::Err(e) => panic!("{:?}", e)
+                }
+            };
+            assert_eq!(msg, message(i));
+        }
+        wait_child(pid);
     }
 
+    println!("Test shm ring: file mapping opened by another process");
+    {
+        let path = std::env::temp_dir().join(format!("thread_pool_shm_{}", std::process::id()));
+        let ring = ShmRing::create(&path, 4, 64).unwrap();
+        let child_path = path.clone();
+        let pid = fork_child(move || {
+            let ring = match ShmRing::open(&child_path) {
+                Result::Ok(ring) => ring,
+                Result::Err(_) => return 1
+            };
+            if ring.slot_count() != 4 || ring.slot_size() != 64 {
+                return 2;
+            }
+            // 等父进程发来的请求，回一条消息
+            let request = loop {
+                if let Result::Ok(msg) = ring.pop() {
+                    break msg;
+                }
+                thread::yield_now();
+            };
+            let mut reply = b"reply to ".to_vec();
+            reply.extend_from_slice(&request);
+            ring.push(&reply).map(|_| 0).unwrap_or(3)
+        });
+
+        ring.push(b"ping").unwrap();
+        wait_child(pid);
+        assert_eq!(ring.pop(), Result::Ok(b"reply to ping".to_vec()));
+        drop(ring);
+
+        println!("Test shm ring: header validation");
+        // 被截断的文件
+        let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(300).unwrap();
+        assert_eq!(ShmRing::open(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        // 不是 ring 的文件
+        fs::write(&path, vec![0xabu8; 4096]).unwrap();
+        assert_eq!(ShmRing::open(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        fs::write(&path, b"tiny").unwrap();
+        assert_eq!(ShmRing::open(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        // 创建者在初始化途中崩溃：state 停在 INITIALIZING
+        drop(ShmRing::create(&path, 4, 64).unwrap());
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[12..16].copy_from_slice(&1u32.to_ne_bytes());
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(ShmRing::open(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        // head/tail 被写坏
+        drop(ShmRing::create(&path, 4, 64).unwrap());
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[128..136].copy_from_slice(&100u64.to_ne_bytes());
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(ShmRing::open(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_file(&path).unwrap();
+    }
 }
 
-/// (实际不会被sync，绕过编译检查用)
-unsafe impl<T: Sized + Send> Sync for RingBuffer<T> {}
+fn test_simulated_pool() {
+    use std::sync::Mutex;
+
+    println!("Test simulated thread pool");
+
+    /// 提交 8 个任务，按完成顺序记录它们的编号
+    fn run(seed: u64) -> (Vec<u64>, Vec<usize>) {
+        let log = Arc::new(Mutex::new(vec![]));
+        let pool = ThreadPool::builder(4).simulated(seed).build();
+        for i in 0..8 {
+            let log = log.clone();
+            pool.queue_task(move || log.lock().unwrap().push(i));
+        }
+        let order = pool.run_until_idle();
+        pool.join();
+        let log = log.lock().unwrap().clone();
+        (order, log)
+    }
 
-type ThreadPoolEntry = Box<dyn FnOnce() + Send + 'static>;
+    let (order, log) = run(42);
+    assert_eq!(order.iter().map(|&x| x as usize).collect::<Vec<_>>(), log);
+    let mut sorted = log.clone();
+    sorted.sort_unstable();
+    assert_eq!(sorted, (0..8).collect::<Vec<_>>());
 
-struct ThreadPool {
-    queue: Arc<RingBuffer<ThreadPoolEntry>>,
-    destroyed_flag: Arc<AtomicBool>,
-    child_threads: Vec<thread::JoinHandle<()>>
+    // 同一个 seed 重放出同样的顺序
+    for _ in 0..3 {
+        assert_eq!(run(42), (order.clone(), log.clone()));
+    }
+    // 不同的 seed 能探索到不同的顺序
+    assert!((0..16).any(|seed| run(seed).1 != log));
+
+    // 单步执行
+    let counter = Arc::new(AtomicUsize::new(0));
+    let pool = ThreadPool::builder(2).simulated(7).build();
+    for _ in 0..3 {
+        let counter = counter.clone();
+        pool.queue_task(move || { counter.fetch_add(1, Ordering::Relaxed); });
+    }
+    assert!(pool.step().is_some());
+    assert_eq!(counter.load(Ordering::Relaxed), 1);
+    pool.join();
+    assert_eq!(counter.load(Ordering::Relaxed), 3);
 }
 
-impl ThreadPool {
+fn test_task() {
+    println!("Test task: inline and boxed closures");
+    let counter = Arc::new(AtomicUsize::new(0));
 
-    fn new(thread_count: usize) -> Self {
-        let destroyed_flag = Arc::new(AtomicBool::new(false));
-        let queue = Arc::new(RingBuffer::<ThreadPoolEntry>::new(16));
-        let mut child_threads = vec![];
-        for i in 0..thread_count {
-            let sub_destroyed_flag = destroyed_flag.clone();
-            let sub_queue = queue.clone();
-            let join_handle = thread::spawn(move || {
-                loop {
-                    match sub_queue.pop() {
-                        Result::Ok(task) => {
-                            task();
-                        },
-                        _ => {
-                            if sub_destroyed_flag.load(Ordering::Relaxed) {
-                                break
-                            } else {
-                                thread::yield_now()
-                            }
-                        }
-                    }
-                }
+    let small = { let c = counter.clone(); move || { c.fetch_add(1, Ordering::Relaxed); } };
+    let large = { let c = counter.clone(); let pad = [1usize; 16]; move || { c.fetch_add(pad[0], Ordering::Relaxed); } };
+    fn is_inline<F: FnOnce() + Send + 'static>(_: &F) -> bool {
+        Task::is_inline::<F>()
+    }
+    assert!(is_inline(&small));
+    assert!(!is_inline(&large));
+
+    Task::new(small.clone()).run();
+    Task::new(large.clone()).run();
+    assert_eq!(counter.load(Ordering::Relaxed), 2);
 
-                println!("Thread #{} destroyed.", i);
+    // 没有执行就被丢弃的任务也要释放它捕获的东西
+    drop(Task::new(small));
+    drop(Task::new(large));
+    assert_eq!(counter.load(Ordering::Relaxed), 2);
+    assert_eq!(Arc::strong_count(&counter), 1);
+}
+
+fn test_task_group() {
+    use std::time::Duration;
+
+    println!("Test task group: results in submission order");
+    {
+        let pool = ThreadPool::builder(4).queue(SegQueue::new()).build();
+        let group = TaskGroup::new(&pool);
+        for i in 0..20u64 {
+            group.spawn(move || {
+                // 越早提交的任务越晚完成
+                thread::sleep(Duration::from_millis(20 - i));
+                if i % 5 == 4 { Result::Err(i) } else { Result::Ok(i * i) }
             });
+        }
+        let results = group.wait();
+        for (i, r) in results.into_iter().enumerate() {
+            let i = i as u64;
+            if i % 5 == 4 {
+                assert_eq!(r, Result::Err(GroupError::Failed(i)));
+            } else {
+                assert_eq!(r, Result::Ok(i * i));
+            }
+        }
+        pool.join();
+    }
 
-            child_threads.push(join_handle);
+    println!("Test task group: cancel on error");
+    {
+        // 单线程保证任务按提交顺序执行
+        let pool = ThreadPool::builder(1).queue(SegQueue::new()).build();
+        let group = TaskGroup::with_cancel_on_error(&pool, true);
+        group.spawn(|| Result::Ok(0));
+        group.spawn(|| Result::Err("boom"));
+        for i in 2..5 {
+            group.spawn(move || Result::Ok(i));
         }
+        let results = group.wait();
+        assert_eq!(results[0], Result::Ok(0));
+        assert_eq!(results[1], Result::Err(GroupError::Failed("boom")));
+        assert!(results[2..].iter().all(|r| *r == Result::Err(GroupError::Cancelled)));
+
+        let group = TaskGroup::<(), ()>::with_cancel_on_error(&pool, true);
+        group.spawn(|| panic!("task panicked on purpose"));
+        group.spawn(|| Result::Ok(()));
+        assert_eq!(group.wait(), vec![Result::Err(GroupError::Panicked), Result::Err(GroupError::Cancelled)]);
+        pool.join();
+    }
 
-        Self {
-            queue: queue,
-            destroyed_flag,
-            child_threads
+    println!("Test task group: wait timeout");
+    {
+        let pool = ThreadPool::new(2);
+        let group = TaskGroup::<(), ()>::new(&pool);
+        group.spawn(|| { thread::sleep(Duration::from_millis(200)); Result::Ok(()) });
+        let group = match group.wait_timeout(Duration::from_millis(10)) {
+            Result::Ok(_) => panic!("should time out"),
+            Result::Err(group) => group
+        };
+        assert_eq!(group.wait_timeout(Duration::from_secs(5)).ok().unwrap(), vec![Result::Ok(())]);
+        pool.join();
+    }
+
+    println!("Test task group: simulated pool");
+    {
+        let pool = ThreadPool::builder(4).simulated(1).build();
+        let group = TaskGroup::<usize, ()>::new(&pool);
+        for i in 0..10 {
+            group.spawn(move || Result::Ok(i));
         }
+        assert_eq!(group.wait(), (0..10).map(Result::Ok).collect::<Vec<_>>());
     }
 
-    fn queue_task<F>(&self, task: F) where F: FnOnce() + Send + 'static {
-        match self.queue.push(Box::new(task)) {
-            Result::Ok(_) => (), 
-            Result::Err(_) => panic!("Thread pending queue is full")
+    println!("Test task group: paused simulated pool");
+    {
+        let pool = ThreadPool::builder(4).simulated(1).build();
+        pool.pause();
+        let group = TaskGroup::<usize, ()>::new(&pool);
+        for i in 0..5 {
+            group.spawn(move || Result::Ok(i));
         }
+        // 暂停期间任务不会执行，只能等到超时
+        let group = match group.wait_timeout(Duration::from_millis(10)) {
+            Result::Ok(_) => panic!("should time out while the pool is paused"),
+            Result::Err(group) => group
+        };
+        pool.resume();
+        assert_eq!(group.wait(), (0..5).map(Result::Ok).collect::<Vec<_>>());
     }
+}
+
+fn test_pause() {
+    use std::sync::atomic::AtomicBool;
+    use std::time::Duration;
 
-    /// join实现方式不是很优雅，因为实现了Drop所以无法将成员变量move out，
-    /// 所以只能用swap vec的方式拿到所有thread handle。
-    fn join(mut self) {
-        self.destroyed_flag.store(true, Ordering::Relaxed);
+    println!("Test pause and resume");
+    {
+        let pool = ThreadPool::builder(2).queue(SegQueue::new()).build();
+        let in_flight_done = Arc::new(AtomicBool::new(false));
+        {
+            let in_flight_done = in_flight_done.clone();
+            pool.queue_task(move || {
+                thread::sleep(Duration::from_millis(100));
+                in_flight_done.store(true, Ordering::SeqCst);
+            });
+        }
+        thread::sleep(Duration::from_millis(20));
+
+        // pause 等正在执行的任务结束后才返回
+        pool.pause();
+        assert!(pool.is_paused());
+        assert!(in_flight_done.load(Ordering::SeqCst));
+
+        // 暂停期间任务照常入队，但不会执行
+        let counter = Arc::new(AtomicUsize::new(0));
+        for _ in 0..10 {
+            let counter = counter.clone();
+            pool.queue_task(move || { counter.fetch_add(1, Ordering::SeqCst); });
+        }
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
 
-        let mut v = Vec::new();
-        std::mem::swap(&mut v, &mut self.child_threads);
-        for handle in v {
-            handle.join().expect("Failed to join thread");
+        pool.resume();
+        assert!(!pool.is_paused());
+        while counter.load(Ordering::SeqCst) < 10 {
+            thread::yield_now();
         }
+        pool.join();
     }
 
+    println!("Test pause with a full queue");
+    {
+        let pool = ThreadPool::builder(2).overflow_policy(OverflowPolicy::Discard).build();
+        pool.pause();
+        let counter = Arc::new(AtomicUsize::new(0));
+        for _ in 0..20 {
+            let counter = counter.clone();
+            pool.queue_task(move || { counter.fetch_add(1, Ordering::SeqCst); });
+        }
+        // 默认队列最多容纳 15 个任务，其余的被丢弃；join 会先 resume 再等队列清空
+        pool.join();
+        assert_eq!(counter.load(Ordering::SeqCst), 15);
+    }
+
+    println!("Test blocking submit with a full queue");
+    {
+        let pool = ThreadPool::builder(2).overflow_policy(OverflowPolicy::Block).build();
+        pool.pause();
+        let counter = Arc::new(AtomicUsize::new(0));
+        let submitted = AtomicUsize::new(0);
+        thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..20 {
+                    let counter = counter.clone();
+                    pool.queue_task(move || { counter.fetch_add(1, Ordering::SeqCst); });
+                    submitted.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+            // 队列满了之后提交方一直等到 resume，有任务出队再继续
+            thread::sleep(Duration::from_millis(50));
+            assert_eq!(submitted.load(Ordering::SeqCst), 15);
+            pool.resume();
+        });
+        assert_eq!(submitted.load(Ordering::SeqCst), 20);
+        pool.join();
+        assert_eq!(counter.load(Ordering::SeqCst), 20);
+    }
 }
 
-impl Drop for ThreadPool {
+fn test_watchdog() {
+    use std::time::Duration;
+
+    println!("Test watchdog");
+    let reports: Arc<Mutex<Vec<OverrunReport>>> = Arc::new(Mutex::new(vec![]));
+    let pool = {
+        let reports = reports.clone();
+        ThreadPool::builder(2)
+            .watchdog(Duration::from_secs(10))
+            .on_overrun(move |r| reports.lock().unwrap().push(r.clone()))
+            .build()
+    };
 
-    fn drop(&mut self) {
-        self.destroyed_flag.store(true, Ordering::Relaxed);
+    // deadline 比默认阈值短，超过 deadline 就会被报告
+    pool.queue_task_with(TaskOptions::new().label("slow").deadline(Duration::from_millis(20)), || {
+        thread::sleep(Duration::from_millis(150));
+    });
+    // 在阈值之内结束的任务不会被报告
+    pool.queue_task_with(TaskOptions::new().label("fast"), || {
+        thread::sleep(Duration::from_millis(10));
+    });
+    pool.queue_task(|| {});
+
+    while pool.metrics().completed < 3 {
+        thread::sleep(Duration::from_millis(5));
     }
 
+    {
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].label.as_deref(), Some("slow"));
+        assert!(reports[0].worker < 2);
+        assert_eq!(reports[0].limit, Duration::from_millis(20));
+        assert!(reports[0].elapsed >= Duration::from_millis(20));
+    }
+    let metrics = pool.metrics();
+    assert_eq!(metrics.submitted, 3);
+    assert_eq!(metrics.completed, 3);
+    assert_eq!(metrics.discarded, 0);
+    assert_eq!(metrics.overruns, 1);
+    pool.join();
 }
 
-fn test_queue() {
-    println!("Test queue: single case");
+fn test_worker_context() {
+    use std::time::Duration;
+
+    println!("Test worker context");
     {
-        let mut q: RingBuffer<u32> = RingBuffer::new(8);
-        for i in 0..7 {
-            println!("Push {}", i);
-            assert_eq!(q.push(i), Result::Ok(()));
+        // 每个工作线程的状态：自己的编号和执行过的任务数
+        let init_count = Arc::new(AtomicUsize::new(0));
+        let pool = {
+            let init_count = init_count.clone();
+            ThreadPool::builder(3)
+                .queue(SegQueue::new())
+                .worker_init(move |index| {
+                    init_count.fetch_add(1, Ordering::SeqCst);
+                    (index, 0usize)
+                })
+                .build()
+        };
+        assert_eq!(ThreadPool::current_worker(), None);
+
+        let done = Arc::new(AtomicUsize::new(0));
+        let per_worker: Arc<Vec<AtomicUsize>> = Arc::new((0..3).map(|_| AtomicUsize::new(0)).collect());
+        for _ in 0..30 {
+            let done = done.clone();
+            let per_worker = per_worker.clone();
+            pool.queue_task(move || {
+                let worker = ThreadPool::current_worker().unwrap();
+                assert!(worker < 3);
+                let count = ThreadPool::with_worker_state(|state: &mut (usize, usize)| {
+                    assert_eq!(state.0, worker);
+                    state.1 += 1;
+                    state.1
+                }).unwrap();
+                // 没有嵌套访问，计数和按编号统计的一致
+                assert_eq!(per_worker[worker].fetch_add(1, Ordering::SeqCst) + 1, count);
+                // 类型不对时拿不到
+                assert!(ThreadPool::with_worker_state(|_: &mut String| ()).is_none());
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        while done.load(Ordering::SeqCst) < 30 {
+            thread::sleep(Duration::from_millis(1));
         }
+        pool.join();
+        assert_eq!(init_count.load(Ordering::SeqCst), 3);
+    }
 
-        println!("Push 7 (out of range)");
-        assert_eq!(q.push(7), Result::Err(7));
+    println!("Test task locals");
+    {
+        #[derive(PartialEq, Debug)]
+        struct RequestId(u32);
 
+        let pool = ThreadPool::new(2);
+        let seen = Arc::new(Mutex::new(vec![]));
         for i in 0..4 {
-            match q.pop() {
-                Result::Ok(x) => println!("Pop returns {}", x),
-                _ => panic!()
-            }
+            let seen = seen.clone();
+            let options = TaskOptions::new().local(RequestId(i)).local("overridden").local("tenant");
+            pool.queue_task_with(options, move || {
+                let id = ThreadPool::with_task_local(|id: &RequestId| id.0).unwrap();
+                let tenant = ThreadPool::with_task_local(|t: &&str| *t).unwrap();
+                seen.lock().unwrap().push((id, tenant));
+            });
+        }
+        {
+            let seen = seen.clone();
+            pool.queue_task(move || {
+                // 没有附带值的任务看不到上一个任务的值
+                assert!(ThreadPool::with_task_local(|_: &RequestId| ()).is_none());
+                seen.lock().unwrap().push((99, "none"));
+            });
         }
+        pool.join();
 
-        println!("Push 5");
-        assert_eq!(q.push(5), Result::Ok( () ));
+        let mut seen = seen.lock().unwrap().clone();
+        seen.sort();
+        assert_eq!(seen, vec![(0, "tenant"), (1, "tenant"), (2, "tenant"), (3, "tenant"), (99, "none")]);
+        assert!(ThreadPool::with_task_local(|_: &RequestId| ()).is_none());
+    }
+
+    println!("Test worker context in simulated pool");
+    {
+        let pool = ThreadPool::builder(4).simulated(7).worker_init(|_| Vec::<i32>::new()).build();
         for i in 0..5 {
-            match q.pop() {
-                Result::Ok(x) => println!("Pop returns {}", x),
-                _ => println!("Pop returns None")
-            }
+            pool.queue_task_with(TaskOptions::new().local(i), || {
+                assert_eq!(ThreadPool::current_worker(), Some(0));
+                let i = ThreadPool::with_task_local(|i: &i32| *i).unwrap();
+                ThreadPool::with_worker_state(|v: &mut Vec<i32>| v.push(i)).unwrap();
+            });
         }
+        let order = pool.run_until_idle();
+        assert_eq!(ThreadPool::current_worker(), None);
 
-        for i in 0..7 {
-            println!("Push {}", i + 8);
-            assert_eq!(q.push(i), Result::Ok(()));
+        // 状态在多次 step 之间保留，顺序就是执行顺序
+        let pushed = Arc::new(Mutex::new(vec![]));
+        {
+            let pushed = pushed.clone();
+            pool.queue_task(move || {
+                ThreadPool::with_worker_state(|v: &mut Vec<i32>| *pushed.lock().unwrap() = v.clone());
+            });
         }
+        pool.join();
+        let expected: Vec<i32> = order.iter().map(|&id| id as i32).collect();
+        assert_eq!(*pushed.lock().unwrap(), expected);
+    }
 
-        for i in 0..7 {
-            match q.pop() {
-                Result::Ok(x) => println!("Pop returns {}", x),
-                _ => println!("Pop returns None")
-            }
+    println!("Test worker state after a panicking task in simulated pool");
+    {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let pool = ThreadPool::builder(1).simulated(3).worker_init(|_| 0u32).build();
+        pool.queue_task(|| {
+            ThreadPool::with_worker_state(|n: &mut u32| *n += 1).unwrap();
+            panic!("task panicked on purpose");
+        });
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| pool.step())).is_err());
+        // 下一个任务仍然以 0 号工作线程的身份执行，并且看得到之前的状态
+        pool.queue_task(|| {
+            assert_eq!(ThreadPool::current_worker(), Some(0));
+            assert_eq!(ThreadPool::with_worker_state(|n: &mut u32| *n), Some(1));
+        });
+        pool.run_until_idle();
+        // 在 with_worker_state 里面 panic 也一样
+        pool.queue_task(|| {
+            ThreadPool::with_worker_state(|n: &mut u32| {
+                *n += 1;
+                panic!("task panicked on purpose");
+            });
+        });
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| pool.step())).is_err());
+        pool.queue_task(|| assert_eq!(ThreadPool::with_worker_state(|n: &mut u32| *n), Some(2)));
+        pool.join();
+    }
+}
+
+fn test_broadcast() {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::time::{Duration, Instant};
+
+    println!("Test broadcast");
+    let pool = ThreadPool::builder(4).queue(SegQueue::new()).build();
+    // 先塞满慢任务，broadcast 不应该排在它们后面
+    let slow_done = Arc::new(AtomicUsize::new(0));
+    for _ in 0..40 {
+        let slow_done = slow_done.clone();
+        pool.queue_task(move || {
+            thread::sleep(Duration::from_millis(20));
+            slow_done.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+    let start = Instant::now();
+    let workers = pool.broadcast(|index| {
+        assert_eq!(ThreadPool::current_worker(), Some(index));
+        index * 10
+    });
+    assert_eq!(workers, vec![0, 10, 20, 30]);
+    assert!(start.elapsed() < Duration::from_millis(150));
+    assert!(slow_done.load(Ordering::SeqCst) < 40);
+
+    // 暂停期间也会执行
+    pool.pause();
+    let calls = Arc::new(AtomicUsize::new(0));
+    {
+        let calls = calls.clone();
+        let results = pool.broadcast(move |_| { calls.fetch_add(1, Ordering::SeqCst); });
+        assert_eq!(results.len(), 4);
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+    pool.resume();
+
+    // 某个工作线程上 panic 时在调用方重新 panic，工作线程本身不受影响
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        pool.broadcast(|index| if index == 1 { panic!("broadcast panicked on purpose") })
+    }));
+    assert!(result.is_err());
+    assert_eq!(pool.broadcast(|index| index).len(), 4);
+
+    pool.join();
+    assert_eq!(slow_done.load(Ordering::SeqCst), 40);
+
+    println!("Test broadcast in simulated pool");
+    let pool = ThreadPool::builder(4).simulated(1).build();
+    assert_eq!(pool.broadcast(|index| ThreadPool::current_worker() == Some(index)), vec![true]);
+    pool.join();
+}
+
+fn test_tenants() {
+    use std::time::Duration;
+
+    println!("Test tenant fair scheduling");
+    {
+        // 只有一个工作线程，执行顺序完全由 scheduler 决定
+        let pool = ThreadPool::builder(1).queue(SegQueue::new()).build();
+        let flood = pool.tenant("flood", TenantOptions::new());
+        let small = pool.tenant("small", TenantOptions::new().weight(2));
+        assert_eq!(small.name(), "small");
+
+        pool.pause();
+        let order = Arc::new(Mutex::new(vec![]));
+        for _ in 0..100 {
+            let order = order.clone();
+            flood.queue_task(move || order.lock().unwrap().push("flood"));
         }
+        for _ in 0..10 {
+            let order = order.clone();
+            small.queue_task(move || order.lock().unwrap().push("small"));
+        }
+        assert_eq!(flood.metrics().queued, 100);
+        pool.resume();
+        pool.join();
+
+        // 按权重 1:2 交替，small 不会排在 100 个 flood 任务后面
+        let order = order.lock().unwrap();
+        assert_eq!(order.len(), 110);
+        assert_eq!(order[..15].iter().filter(|&&x| x == "small").count(), 10);
+        assert_eq!(&order[..6], &["flood", "small", "small", "flood", "small", "small"]);
+        assert_eq!(flood.metrics(), TenantMetrics { submitted: 100, completed: 100, queued: 0, running: 0 });
+        assert_eq!(small.metrics().completed, 10);
+    }
+
+    println!("Test tenant concurrency cap");
+    {
+        let pool = ThreadPool::new(4);
+        let capped = pool.tenant("capped", TenantOptions::new().max_concurrency(2));
+        let current = Arc::new(AtomicUsize::new(0));
+        let max_seen = Arc::new(AtomicUsize::new(0));
+        for _ in 0..20 {
+            let current = current.clone();
+            let max_seen = max_seen.clone();
+            capped.queue_task(move || {
+                let now = current.fetch_add(1, Ordering::SeqCst) + 1;
+                max_seen.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(5));
+                current.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+        // 主队列的任务不受 tenant 的并发上限影响
+        let plain = Arc::new(AtomicUsize::new(0));
+        for _ in 0..10 {
+            let plain = plain.clone();
+            pool.queue_task(move || { plain.fetch_add(1, Ordering::SeqCst); });
+        }
+        // 同名的 tenant 是同一个队列
+        assert_eq!(pool.tenant("capped", TenantOptions::new().max_concurrency(2)).metrics().submitted, 20);
+        pool.join();
+
+        assert_eq!(max_seen.load(Ordering::SeqCst), 2);
+        assert_eq!(plain.load(Ordering::SeqCst), 10);
+        assert_eq!(capped.metrics().completed, 20);
+    }
+}
+
+fn test_rate_limit() {
+    use std::time::Duration;
+
+    fn wait_for<F>(f: F) where F: Fn() -> bool {
+        while !f() {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    println!("Test rate limiter");
+    {
+        let clock = MockClock::new();
+        let limiter = RateLimiter::new(2, Duration::from_millis(100)).burst(3).clock(clock.clone());
+        // 开始时令牌是满的
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+        // 每 50ms 补充一个
+        clock.advance(Duration::from_millis(50));
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+        // 最多攒 burst 个
+        clock.advance(Duration::from_secs(10));
+        for _ in 0..3 {
+            assert!(limiter.try_acquire());
+        }
+        assert!(!limiter.try_acquire());
+    }
+
+    println!("Test rate limited pool");
+    {
+        let clock = MockClock::new();
+        let pool = ThreadPool::builder(2)
+            .queue(SegQueue::new())
+            .rate_limit(RateLimiter::new(5, Duration::from_secs(1)).clock(clock.clone()))
+            .build();
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..12 {
+            let done = done.clone();
+            pool.queue_task(move || { done.fetch_add(1, Ordering::SeqCst); });
+        }
+
+        // 超出限制的任务留在队列里，时钟前进之后才执行
+        wait_for(|| done.load(Ordering::SeqCst) == 5);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(done.load(Ordering::SeqCst), 5);
+        clock.advance(Duration::from_secs(1));
+        wait_for(|| done.load(Ordering::SeqCst) == 10);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(done.load(Ordering::SeqCst), 10);
+        assert_eq!(pool.metrics().discarded, 0);
+        clock.advance(Duration::from_secs(1));
+        pool.join();
+        assert_eq!(done.load(Ordering::SeqCst), 12);
+    }
+
+    println!("Test rate limited tenant");
+    {
+        let clock = MockClock::new();
+        let pool = ThreadPool::new(2);
+        let limited = pool.tenant("compaction", TenantOptions::new()
+            .rate_limit(RateLimiter::new(1, Duration::from_secs(1)).clock(clock.clone())));
+        let other = pool.tenant("other", TenantOptions::new());
+        for _ in 0..3 {
+            limited.queue_task(|| {});
+        }
+        for _ in 0..5 {
+            other.queue_task(|| {});
+        }
+
+        // 被限速的 tenant 不影响其他 tenant
+        wait_for(|| other.metrics().completed == 5 && limited.metrics().completed == 1);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(limited.metrics().completed, 1);
+        assert_eq!(limited.metrics().queued, 2);
+        clock.advance(Duration::from_secs(1));
+        wait_for(|| limited.metrics().completed == 2);
+        // join 会等被限速的任务也执行完
+        clock.advance(Duration::from_secs(1));
+        pool.join();
+        assert_eq!(limited.metrics().completed, 3);
     }
 }
 
+fn test_topology() {
+    use std::fs;
+    use thread_pool::affinity::parse_cpu_list;
+
+    println!("Test cpu topology");
+    assert_eq!(parse_cpu_list("0-3,8,10-11\n").unwrap(), vec![0, 1, 2, 3, 8, 10, 11]);
+    assert_eq!(parse_cpu_list("").unwrap(), Vec::<usize>::new());
+    assert!(parse_cpu_list("3-1").is_err());
+    assert!(parse_cpu_list("a").is_err());
+
+    // 假的 sysfs：2 个 NUMA 节点，每个节点 2 个物理核，每个核 2 个超线程（cpu n 和 n + 4 是同一个核），cpu7 离线
+    let root = std::env::temp_dir().join(format!("thread_pool_sysfs_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for cpu in 0..8 {
+        let topology = root.join(format!("cpu/cpu{}/topology", cpu));
+        fs::create_dir_all(&topology).unwrap();
+        fs::write(topology.join("core_id"), format!("{}\n", cpu % 4)).unwrap();
+        fs::write(topology.join("physical_package_id"), format!("{}\n", cpu % 4 / 2)).unwrap();
+    }
+    fs::create_dir_all(root.join("cpu/cpufreq")).unwrap();
+    fs::write(root.join("cpu/online"), "0-6\n").unwrap();
+    for (node, cpus) in [(0, "0-1,4-5"), (1, "2-3,6-7")] {
+        fs::create_dir_all(root.join(format!("node/node{}", node))).unwrap();
+        fs::write(root.join(format!("node/node{}/cpulist", node)), cpus).unwrap();
+    }
+
+    let topology = CpuTopology::read_from(&root).unwrap();
+    assert_eq!(topology.cpus().len(), 7);
+    assert_eq!(topology.physical_cores(), vec![vec![0, 4], vec![1, 5], vec![2, 6], vec![3]]);
+    assert_eq!(topology.numa_nodes(), vec![(0, vec![0, 1, 4, 5]), (1, vec![2, 3, 6])]);
+
+    // 没有 node 目录时都属于 0 号节点
+    fs::remove_dir_all(root.join("node")).unwrap();
+    let topology = CpuTopology::read_from(&root).unwrap();
+    assert_eq!(topology.numa_nodes().len(), 1);
+    fs::remove_dir_all(&root).unwrap();
+
+    assert!(CpuTopology::read_from(&std::env::temp_dir().join("thread_pool_no_such_sysfs")).is_err());
+}
+
+#[cfg(target_os = "linux")]
+fn test_affinity() {
+    use thread_pool::affinity::current_thread_affinity;
+
+    println!("Test worker affinity");
+    let pool = ThreadPool::builder(2).affinity(Affinity::Cores(vec![0])).build();
+    assert_eq!(pool.broadcast(|_| current_thread_affinity().unwrap()), vec![vec![0], vec![0]]);
+    pool.join();
+
+    let cores = CpuTopology::read().unwrap().physical_cores();
+    let pool = ThreadPool::builder(64).affinity(Affinity::PhysicalCores).build();
+    let pinned = pool.broadcast(|_| current_thread_affinity().unwrap());
+    assert_eq!(pinned, cores);
+    pool.join();
+
+    println!("Test numa local queues");
+    let pool = ThreadPool::builder(3).numa_local_queues(true).overflow_policy(OverflowPolicy::Block).build();
+    let counter = Arc::new(AtomicUsize::new(0));
+    for _ in 0..100 {
+        let counter = counter.clone();
+        pool.queue_task(move || { counter.fetch_add(1, Ordering::SeqCst); });
+    }
+    pool.join();
+    assert_eq!(counter.load(Ordering::SeqCst), 100);
+}
+
+fn test_events() {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::time::Duration;
+
+    /// (kind, timestamp, task_id, worker, label)
+    type Recorded = (EventKind, Duration, Option<u64>, Option<usize>, Option<String>);
+
+    struct Recorder(Mutex<Vec<Recorded>>);
+
+    impl EventSink for Recorder {
+        fn event(&self, e: &Event) {
+            self.0.lock().unwrap().push((e.kind, e.timestamp, e.task_id, e.worker, e.label.map(|s| s.to_string())));
+        }
+    }
+
+    println!("Test events");
+    let recorder = Arc::new(Recorder(Mutex::new(vec![])));
+    let pool = ThreadPool::builder(2).event_sink(recorder.clone()).build();
+    pool.queue_task(|| {});
+    pool.queue_task_with(TaskOptions::new().label("named"), || {});
+    pool.queue_task(|| {});
+    while pool.metrics().completed < 3 {
+        thread::yield_now();
+    }
+    pool.join();
+
+    let events = recorder.0.lock().unwrap();
+    for id in 0..3 {
+        let of = |kind| events.iter().find(|e| e.0 == kind && e.2 == Some(id)).unwrap();
+        let (submitted, started, finished) = (of(EventKind::TaskSubmitted), of(EventKind::TaskStarted), of(EventKind::TaskFinished));
+        assert!(submitted.1 <= started.1 && started.1 <= finished.1);
+        assert_eq!(submitted.3, None);
+        assert!(started.3.unwrap() < 2);
+        assert_eq!(started.3, finished.3);
+        assert_eq!(started.4.as_deref(), if id == 1 { Some("named") } else { None });
+    }
+    assert!(events.iter().any(|e| e.0 == EventKind::WorkerParked));
+    assert_eq!(events.iter().filter(|e| e.0 == EventKind::PoolShutdown).count(), 1);
+    assert_eq!(events.last().unwrap().0, EventKind::PoolShutdown);
+    assert!(!events.iter().any(|e| e.0 == EventKind::TaskPanicked));
+
+    println!("Test chrome trace export");
+    let trace = Arc::new(ChromeTraceSink::new());
+    let pool = ThreadPool::builder(1).simulated(3).event_sink(trace.clone()).build();
+    pool.queue_task_with(TaskOptions::new().label("say \"hi\""), || {});
+    pool.queue_task(|| panic!("task panicked on purpose"));
+    // 模拟模式下任务的 panic 传到调用 step 的地方
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| pool.run_until_idle()));
+    pool.run_until_idle();
+    // 2 个 TaskSubmitted、2 个 TaskStarted、1 个 TaskFinished、1 个 TaskPanicked
+    assert_eq!(trace.len(), 6);
+    pool.join();
+
+    let path = std::env::temp_dir().join(format!("thread_pool_trace_{}.json", std::process::id()));
+    trace.save(&path).unwrap();
+    let json = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(json.starts_with(r#"{"traceEvents":["#));
+    assert!(json.contains(r#"{"name":"thread_name","ph":"M","pid":1,"tid":0,"args":{"name":"worker #0"}}"#));
+    assert!(json.contains(r#"{"name":"say \"hi\"","ph":"B","#));
+    assert!(json.contains(r#"{"name":"task #1 (panicked)","ph":"E","#));
+    assert!(json.contains(r#"{"name":"shutdown","ph":"i","#));
+}
+
+fn test_unbounded_pool() {
+    println!("Test thread pool with unbounded queue");
+    let counter = Arc::new(AtomicUsize::new(0));
+    let thread_pool = ThreadPool::builder(3)
+        .queue(SegQueue::new())
+        .build();
+    // 远超默认 RingBuffer 的 16 个任务
+    for _ in 0..1000 {
+        let counter = counter.clone();
+        thread_pool.queue_task(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    thread_pool.join();
+    assert_eq!(counter.load(Ordering::Relaxed), 1000);
+}
+
 fn main() {
     use std::time::Duration;
     println!("Testing ringbuffer...");
     test_queue();
+    test_seg_queue();
+
+    println!();
+    println!("Testing channel...");
+    test_channel();
+
+    #[cfg(target_os = "linux")]
+    {
+        println!();
+        println!("Testing shm ring...");
+        test_shm_ring();
+    }
 
     println!();
     println!("Testing thread pool...");
+    test_task();
+    test_unbounded_pool();
+    test_simulated_pool();
+    test_task_group();
+    test_pause();
+    test_watchdog();
+    test_worker_context();
+    test_broadcast();
+    test_tenants();
+    test_rate_limit();
+    test_events();
+    test_topology();
+    #[cfg(target_os = "linux")]
+    test_affinity();
+
     let thread_pool = ThreadPool::new(3);
     for i in 0..4 {
         thread_pool.queue_task(move || {
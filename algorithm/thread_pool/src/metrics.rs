@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// ThreadPool 的运行统计，全部是单调递增的计数器
+#[derive(Default)]
+pub(crate) struct Metrics {
+    submitted: AtomicU64,
+    completed: AtomicU64,
+    discarded: AtomicU64,
+    overruns: AtomicU64
+}
+
+/// 某一时刻的统计值
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct MetricsSnapshot {
+    /// 成功放进队列的任务数
+    pub submitted: u64,
+    /// 执行完的任务数
+    pub completed: u64,
+    /// 因为队列满被 `OverflowPolicy::Discard` 丢弃的任务数
+    pub discarded: u64,
+    /// 被 watchdog 报告运行超时的任务数
+    pub overruns: u64
+}
+
+impl Metrics {
+
+    pub fn record_submitted(&self) {
+        self.submitted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_completed(&self) {
+        self.completed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_discarded(&self) {
+        self.discarded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_overrun(&self) {
+        self.overruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            submitted: self.submitted.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+            discarded: self.discarded.load(Ordering::Relaxed),
+            overruns: self.overruns.load(Ordering::Relaxed)
+        }
+    }
+
+}
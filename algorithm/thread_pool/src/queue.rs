@@ -0,0 +1,10 @@
+/// ThreadPool 使用的任务队列接口，`RingBuffer` 和 `SegQueue` 都实现了它。
+///
+/// 语义和 `RingBuffer` 保持一致：push 失败时把值原样还回去，pop 在队列为空时返回 `Err(())`。
+pub trait Queue<T>: Send + Sync {
+
+    fn push(&self, val: T) -> Result<(), T>;
+
+    fn pop(&self) -> Result<T, ()>;
+
+}
@@ -0,0 +1,146 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// RateLimiter 使用的时钟，返回从某个固定时刻开始经过的时间，必须单调不减
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Duration;
+}
+
+/// 基于 Instant 的真实时钟
+pub struct SystemClock {
+    start: Instant
+}
+
+impl SystemClock {
+
+    pub fn new() -> Self {
+        Self { start: Instant::now() }
+    }
+
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// 只在调用 advance 时前进的时钟，用来在测试里精确控制 RateLimiter。clone 出来的 MockClock 共享同一个时间
+#[derive(Clone, Default)]
+pub struct MockClock {
+    now: Arc<Mutex<Duration>>
+}
+
+impl MockClock {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, d: Duration) {
+        *self.now.lock().unwrap() += d;
+    }
+
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+}
+
+struct Bucket {
+    /// 以 1/interval_nanos 个令牌为单位，这样补充令牌全是整数运算，MockClock 下结果是精确的
+    tokens: u128,
+    last: Duration
+}
+
+/// 令牌桶：每 interval 补充 tasks 个令牌，最多攒 burst 个，每开始执行一个任务消耗一个。
+///
+/// 挂在 ThreadPool（`ThreadPoolBuilder::rate_limit`）或者 tenant（`TenantOptions::rate_limit`）上。
+/// 没有令牌时任务留在队列里等待，不会被拒绝或丢弃。
+pub struct RateLimiter {
+    tasks: u128,
+    interval: u128,
+    burst: u128,
+    clock: Box<dyn Clock>,
+    /// 第一次使用时才初始化，之前可以随意修改 burst 和 clock
+    bucket: Mutex<Option<Bucket>>
+}
+
+impl RateLimiter {
+
+    /// 每 interval 最多开始 tasks 个任务。默认 burst 等于 tasks，使用 SystemClock
+    pub fn new(tasks: u32, interval: Duration) -> Self {
+        assert!(tasks > 0, "RateLimiter must allow at least one task per interval");
+        assert!(interval > Duration::from_secs(0), "RateLimiter interval must be positive");
+        Self {
+            tasks: tasks as u128,
+            interval: interval.as_nanos(),
+            burst: tasks as u128,
+            clock: Box::new(SystemClock::new()),
+            bucket: Mutex::new(None)
+        }
+    }
+
+    /// 最多攒多少个令牌，也就是空闲一段时间后最多能一下子开始多少个任务。开始时令牌是满的
+    pub fn burst(mut self, burst: u32) -> Self {
+        assert!(burst > 0, "RateLimiter burst must be positive");
+        self.burst = burst as u128;
+        self
+    }
+
+    pub fn clock<C>(mut self, clock: C) -> Self where C: Clock + 'static {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// 有令牌时消耗一个并返回 true
+    pub fn try_acquire(&self) -> bool {
+        let mut bucket = self.bucket.lock().unwrap();
+        let bucket = self.refill(&mut bucket);
+        if bucket.tokens >= self.interval {
+            bucket.tokens -= self.interval;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 把 try_acquire 拿到但没有用上的令牌还回去
+    pub(crate) fn release(&self) {
+        let mut bucket = self.bucket.lock().unwrap();
+        let bucket = self.refill(&mut bucket);
+        bucket.tokens = (bucket.tokens + self.interval).min(self.burst * self.interval);
+    }
+
+    fn refill<'a>(&self, bucket: &'a mut Option<Bucket>) -> &'a mut Bucket {
+        let now = self.clock.now();
+        let capacity = self.burst * self.interval;
+        let bucket = bucket.get_or_insert_with(|| Bucket { tokens: capacity, last: now });
+        if now > bucket.last {
+            let elapsed = (now - bucket.last).as_nanos();
+            bucket.tokens = (bucket.tokens + elapsed * self.tasks).min(capacity);
+            bucket.last = now;
+        }
+        bucket
+    }
+
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("tasks", &self.tasks)
+            .field("interval", &Duration::from_nanos(self.interval as u64))
+            .field("burst", &self.burst)
+            .finish()
+    }
+}
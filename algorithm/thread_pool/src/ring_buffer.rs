@@ -0,0 +1,137 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::cell::UnsafeCell;
+
+use crate::queue::Queue;
+
+/// 一个勉强能用的RingBuffer
+///
+/// 每个 slot 带一个 stamp（Vyukov 的 bounded MPMC queue 的做法）：
+/// head/tail 只增不减，stamp 记录这个 slot 下一次轮到哪个位置读写：
+/// 等待位置 pos 写入时是 2 * pos，写完等待读取时是 2 * pos + 1。
+/// 这样 CAS 抢到位置和真正读写 slot 之间的窗口就不会被别的线程看到半成品，
+/// 也不会因为下标取模之后的 ABA 把满队列当成空队列。
+pub struct RingBuffer<T> where T: Sized + Send {
+    /// 使用 UnsafeCell 让我们可以在 &self 里对 Vec 进行操作。
+    /// 这样才能在正常的使用里避免多线程加锁（不然就需要Arc<RwLock<RingBuffer>>>，破坏了无锁队列的初衷。。）
+    ///
+    /// 保持原来"留一个空位"的容量：大小为 size 的 RingBuffer 最多容纳 size - 1 个元素，所以只分配 size - 1 个 slot。
+    arr: Vec<Slot<T>>,
+    head: AtomicUsize,
+    tail: AtomicUsize
+}
+
+struct Slot<T> {
+    stamp: AtomicUsize,
+    value: UnsafeCell<Option<T>>
+}
+
+impl<T> RingBuffer<T> where T: Sized + Send {
+
+    pub fn new(size: usize) -> Self {
+        assert!(size > 1);
+        let mut v = Vec::new();
+        for i in 0..size - 1 {
+            v.push(Slot {
+                stamp: AtomicUsize::new(i * 2),
+                value: None.into()
+            });
+        }
+        Self {
+            arr: v,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0)
+        }
+    }
+
+    pub fn push(&self, val: T) -> Result<(), T> {
+        let cap = self.capacity();
+
+        // CAS
+        let mut last_tail = self.tail.load(Ordering::Relaxed);
+        let slot = loop {
+            let slot = &self.arr[last_tail % cap];
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            // stamp == 2 * tail: slot 空着，轮到我们写
+            // stamp < 2 * tail: 上一圈的值还没被读走，队列满了
+            // stamp > 2 * tail: 别的线程已经抢先写了，重新读一下 tail
+            let diff = stamp.wrapping_sub(last_tail.wrapping_mul(2)) as isize;
+            if diff == 0 {
+                match self.tail.compare_exchange_weak(last_tail, last_tail.wrapping_add(1),
+                    Ordering::Relaxed, Ordering::Relaxed) {
+                    Result::Ok(_) => break slot,
+                    Result::Err(x) => last_tail = x
+                }
+            } else if diff < 0 {
+                return Result::Err(val);
+            } else {
+                last_tail = self.tail.load(Ordering::Relaxed);
+            }
+        };
+
+        unsafe { // SAFETY: stamp 保证了同一时间只有一个线程访问这个cell
+            *slot.value.get() = Some(val)
+        }
+        slot.stamp.store(last_tail.wrapping_mul(2).wrapping_add(1), Ordering::Release);
+        Result::Ok(())
+    }
+
+    pub fn pop(&self) -> Result<T, ()> {
+        let cap = self.capacity();
+
+        let mut last_head = self.head.load(Ordering::Relaxed);
+        let slot = loop {
+            let slot = &self.arr[last_head % cap];
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            // stamp == 2 * head + 1: 已经写好了，可以读
+            // stamp < 2 * head + 1: 还没写进来，队列为空
+            let diff = stamp.wrapping_sub(last_head.wrapping_mul(2).wrapping_add(1)) as isize;
+            if diff == 0 {
+                match self.head.compare_exchange_weak(last_head, last_head.wrapping_add(1),
+                    Ordering::Relaxed, Ordering::Relaxed) {
+                    Result::Ok(_) => break slot,
+                    Result::Err(x) => last_head = x
+                }
+            } else if diff < 0 {
+                return Result::Err(());
+            } else {
+                last_head = self.head.load(Ordering::Relaxed);
+            }
+        };
+
+        let elem = unsafe { // SAFETY: stamp 保证了同一时间只有一个线程访问这个cell
+            (*slot.value.get()).take()
+        };
+        // 下一圈轮到 head + cap 来写这个 slot
+        slot.stamp.store(last_head.wrapping_add(cap).wrapping_mul(2), Ordering::Release);
+
+        match elem {
+            Some(x) => Result::Ok(x),
+            None => Result::Err(())
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.arr.len() + 1
+    }
+
+    /// 最多能同时容纳的元素个数，即 size - 1
+    pub fn capacity(&self) -> usize {
+        self.arr.len()
+    }
+
+}
+
+/// (实际不会被sync，绕过编译检查用)
+unsafe impl<T: Sized + Send> Sync for RingBuffer<T> {}
+
+impl<T> Queue<T> for RingBuffer<T> where T: Sized + Send {
+
+    fn push(&self, val: T) -> Result<(), T> {
+        RingBuffer::push(self, val)
+    }
+
+    fn pop(&self) -> Result<T, ()> {
+        RingBuffer::pop(self)
+    }
+
+}
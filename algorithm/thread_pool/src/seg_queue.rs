@@ -0,0 +1,331 @@
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{self, AtomicPtr, AtomicUsize, Ordering};
+use std::thread;
+
+use crate::queue::Queue;
+
+// 思路参考 crossbeam 的 SegQueue：
+// 队列由若干个固定大小的 Block 串成链表，head/tail 是单调递增的 index，
+// index 的低位 (SHIFT) 留作标记，剩余部分 % LAP 就是在当前 Block 里的 offset。
+// 每个 Block 只有 BLOCK_CAP 个 slot，offset == BLOCK_CAP 表示"正在切换到下一个 Block"。
+
+/// slot 已经写入了值
+const WRITE: usize = 1;
+/// slot 里的值已经被读走
+const READ: usize = 2;
+/// Block 正在被回收，最后一个读完的线程负责释放它
+const DESTROY: usize = 4;
+
+const LAP: usize = 32;
+const BLOCK_CAP: usize = LAP - 1;
+const SHIFT: usize = 1;
+/// head index 上的标记：当前 Block 之后已经有下一个 Block 了，pop 时不需要再去检查 tail
+const HAS_NEXT: usize = 1;
+
+struct Slot<T> {
+    value: UnsafeCell<MaybeUninit<T>>,
+    state: AtomicUsize
+}
+
+impl<T> Slot<T> {
+
+    fn new() -> Self {
+        Self {
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+            state: AtomicUsize::new(0)
+        }
+    }
+
+    /// push 方在抢到 slot 和真正写入之间有一个窗口，pop 方需要等它写完
+    fn wait_write(&self) {
+        while self.state.load(Ordering::Acquire) & WRITE == 0 {
+            thread::yield_now();
+        }
+    }
+
+}
+
+struct Block<T> {
+    next: AtomicPtr<Block<T>>,
+    slots: [Slot<T>; BLOCK_CAP]
+}
+
+impl<T> Block<T> {
+
+    fn new() -> Box<Self> {
+        Box::new(Self {
+            next: AtomicPtr::new(ptr::null_mut()),
+            slots: std::array::from_fn(|_| Slot::new())
+        })
+    }
+
+    fn wait_next(&self) -> *mut Block<T> {
+        loop {
+            let next = self.next.load(Ordering::Acquire);
+            if !next.is_null() {
+                return next;
+            }
+            thread::yield_now();
+        }
+    }
+
+    /// 回收一个 Block。
+    ///
+    /// 读走最后一个 slot 的线程会从 0 开始检查；如果还有别的线程没读完某个 slot，
+    /// 就在那个 slot 上打 DESTROY 标记然后撒手，由那个线程读完后从下一个 slot 接着检查。
+    /// 这样保证 Block 只会在所有 slot 都被读完之后被释放一次。
+    unsafe fn destroy(this: *mut Block<T>, start: usize) {
+        // 最后一个 slot 不需要检查：destroy 就是读走它的线程发起的
+        for i in start..BLOCK_CAP - 1 {
+            let slot = &(*this).slots[i];
+            if slot.state.load(Ordering::Acquire) & READ == 0
+                && slot.state.fetch_or(DESTROY, Ordering::AcqRel) & READ == 0 {
+                return;
+            }
+        }
+        drop(Box::from_raw(this));
+    }
+
+}
+
+/// head/tail 独占一条 cache line，避免 push 和 pop 互相 false sharing
+#[repr(align(64))]
+struct Position<T> {
+    index: AtomicUsize,
+    block: AtomicPtr<Block<T>>
+}
+
+impl<T> Position<T> {
+
+    fn new() -> Self {
+        Self {
+            index: AtomicUsize::new(0),
+            block: AtomicPtr::new(ptr::null_mut())
+        }
+    }
+
+}
+
+/// 由固定大小分段串成的无界无锁队列，MPMC。
+///
+/// 和 `RingBuffer` 不同，push 永远不会失败；读空的分段由最后一个读者负责释放。
+pub struct SegQueue<T> where T: Send {
+    head: Position<T>,
+    tail: Position<T>,
+    _marker: PhantomData<T>
+}
+
+unsafe impl<T: Send> Send for SegQueue<T> {}
+unsafe impl<T: Send> Sync for SegQueue<T> {}
+
+impl<T> SegQueue<T> where T: Send {
+
+    pub fn new() -> Self {
+        Self {
+            head: Position::new(),
+            tail: Position::new(),
+            _marker: PhantomData
+        }
+    }
+
+    pub fn push(&self, val: T) {
+        let mut tail = self.tail.index.load(Ordering::Acquire);
+        let mut block = self.tail.block.load(Ordering::Acquire);
+        let mut next_block: Option<Box<Block<T>>> = None;
+
+        loop {
+            let offset = (tail >> SHIFT) % LAP;
+
+            // 别的线程正在装下一个 Block，等它装完
+            if offset == BLOCK_CAP {
+                thread::yield_now();
+                tail = self.tail.index.load(Ordering::Acquire);
+                block = self.tail.block.load(Ordering::Acquire);
+                continue;
+            }
+
+            // 要填的是当前 Block 的最后一个 slot，提前把下一个 Block 分配好，缩短其他线程等待的时间
+            if offset + 1 == BLOCK_CAP && next_block.is_none() {
+                next_block = Some(Block::new());
+            }
+
+            // 第一次 push，初始化第一个 Block
+            if block.is_null() {
+                let new = Box::into_raw(Block::new());
+                if self.tail.block.compare_exchange(block, new,
+                    Ordering::Release, Ordering::Relaxed).is_ok() {
+                    self.head.block.store(new, Ordering::Release);
+                    block = new;
+                } else {
+                    // SAFETY: new 没有被发布出去，还是我们独占的
+                    next_block = Some(unsafe { Box::from_raw(new) });
+                    tail = self.tail.index.load(Ordering::Acquire);
+                    block = self.tail.block.load(Ordering::Acquire);
+                    continue;
+                }
+            }
+
+            let new_tail = tail + (1 << SHIFT);
+            match self.tail.index.compare_exchange_weak(tail, new_tail,
+                Ordering::SeqCst, Ordering::Acquire) {
+                Result::Ok(_) => unsafe {
+                    // 抢到了当前 Block 的最后一个 slot，负责把下一个 Block 接上
+                    if offset + 1 == BLOCK_CAP {
+                        let next_block = Box::into_raw(next_block.take().unwrap());
+                        let next_index = new_tail.wrapping_add(1 << SHIFT);
+
+                        self.tail.block.store(next_block, Ordering::Release);
+                        self.tail.index.store(next_index, Ordering::Release);
+                        (*block).next.store(next_block, Ordering::Release);
+                    }
+
+                    // SAFETY: CAS 成功意味着这个 slot 只归当前线程写
+                    let slot = &(*block).slots[offset];
+                    slot.value.get().write(MaybeUninit::new(val));
+                    slot.state.fetch_or(WRITE, Ordering::Release);
+                    return;
+                },
+                Result::Err(t) => {
+                    tail = t;
+                    block = self.tail.block.load(Ordering::Acquire);
+                }
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Result<T, ()> {
+        let mut head = self.head.index.load(Ordering::Acquire);
+        let mut block = self.head.block.load(Ordering::Acquire);
+
+        loop {
+            let offset = (head >> SHIFT) % LAP;
+
+            if offset == BLOCK_CAP {
+                thread::yield_now();
+                head = self.head.index.load(Ordering::Acquire);
+                block = self.head.block.load(Ordering::Acquire);
+                continue;
+            }
+
+            let mut new_head = head + (1 << SHIFT);
+
+            if new_head & HAS_NEXT == 0 {
+                atomic::fence(Ordering::SeqCst);
+                let tail = self.tail.index.load(Ordering::Relaxed);
+
+                // 队列为空
+                if head >> SHIFT == tail >> SHIFT {
+                    return Result::Err(());
+                }
+
+                // head 和 tail 不在同一个 Block 里，说明后面一定还有 Block
+                if (head >> SHIFT) / LAP != (tail >> SHIFT) / LAP {
+                    new_head |= HAS_NEXT;
+                }
+            }
+
+            // 第一个 Block 还在初始化中
+            if block.is_null() {
+                thread::yield_now();
+                head = self.head.index.load(Ordering::Acquire);
+                block = self.head.block.load(Ordering::Acquire);
+                continue;
+            }
+
+            match self.head.index.compare_exchange_weak(head, new_head,
+                Ordering::SeqCst, Ordering::Acquire) {
+                Result::Ok(_) => unsafe {
+                    // 读到了当前 Block 的最后一个 slot，把 head 挪到下一个 Block
+                    if offset + 1 == BLOCK_CAP {
+                        let next = (*block).wait_next();
+                        let mut next_index = (new_head & !HAS_NEXT).wrapping_add(1 << SHIFT);
+                        if !(*next).next.load(Ordering::Relaxed).is_null() {
+                            next_index |= HAS_NEXT;
+                        }
+
+                        self.head.block.store(next, Ordering::Release);
+                        self.head.index.store(next_index, Ordering::Release);
+                    }
+
+                    let slot = &(*block).slots[offset];
+                    slot.wait_write();
+                    let val = slot.value.get().read().assume_init();
+
+                    // 回收 Block：读完最后一个 slot 的线程发起；
+                    // 或者发起者已经在这个 slot 上留下了 DESTROY 标记，由当前线程接手
+                    if offset + 1 == BLOCK_CAP {
+                        Block::destroy(block, 0);
+                    } else if slot.state.fetch_or(READ, Ordering::AcqRel) & DESTROY != 0 {
+                        Block::destroy(block, offset + 1);
+                    }
+
+                    return Result::Ok(val);
+                },
+                Result::Err(h) => {
+                    head = h;
+                    block = self.head.block.load(Ordering::Acquire);
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let head = self.head.index.load(Ordering::SeqCst);
+        let tail = self.tail.index.load(Ordering::SeqCst);
+        head >> SHIFT == tail >> SHIFT
+    }
+
+}
+
+impl<T> Default for SegQueue<T> where T: Send {
+
+    fn default() -> Self {
+        Self::new()
+    }
+
+}
+
+impl<T> Drop for SegQueue<T> where T: Send {
+
+    fn drop(&mut self) {
+        let mut head = *self.head.index.get_mut() & !((1 << SHIFT) - 1);
+        let tail = *self.tail.index.get_mut() & !((1 << SHIFT) - 1);
+        let mut block = *self.head.block.get_mut();
+
+        unsafe { // SAFETY: &mut self，没有其他线程在访问
+            while head != tail {
+                let offset = (head >> SHIFT) % LAP;
+                if offset < BLOCK_CAP {
+                    let slot = &(*block).slots[offset];
+                    (*slot.value.get()).as_mut_ptr().drop_in_place();
+                } else {
+                    let next = *(*block).next.get_mut();
+                    drop(Box::from_raw(block));
+                    block = next;
+                }
+                head = head.wrapping_add(1 << SHIFT);
+            }
+
+            if !block.is_null() {
+                drop(Box::from_raw(block));
+            }
+        }
+    }
+
+}
+
+impl<T> Queue<T> for SegQueue<T> where T: Send {
+
+    fn push(&self, val: T) -> Result<(), T> {
+        SegQueue::push(self, val);
+        Result::Ok(())
+    }
+
+    fn pop(&self) -> Result<T, ()> {
+        SegQueue::pop(self)
+    }
+
+}
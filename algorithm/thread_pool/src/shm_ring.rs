@@ -0,0 +1,331 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// RingBuffer 的跨进程版本：放在 mmap 出来的共享内存里，只存放长度前缀的字节消息。
+//
+// 内存布局（全部是固定布局，不包含指针，不同进程映射到不同地址也没关系）：
+//
+//   [Header: 192 bytes][Slot 0][Slot 1]...[Slot n-1]
+//   Slot = [stamp: u64][len: u32][_pad: u32][payload: slot_size 向上对齐到 8]
+//
+// stamp 的含义和 RingBuffer 一样：等待位置 pos 写入时是 2 * pos，写完等待读取时是 2 * pos + 1。
+
+const MAGIC: u64 = u64::from_le_bytes(*b"TPSHMRNG");
+const VERSION: u32 = 1;
+
+/// Header.state：新建的文件全是 0，创建者填好 Header 之后才会变成 READY。
+/// 如果创建者在初始化途中崩溃，state 会停在 INITIALIZING，打开方据此拒绝这块内存。
+const STATE_INITIALIZING: u32 = 1;
+const STATE_READY: u32 = 2;
+
+/// open 时最多等待创建者完成初始化的时间
+const INIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[repr(C)]
+struct Header {
+    magic: u64,
+    version: u32,
+    state: AtomicU32,
+    slot_count: u64,
+    slot_size: u64,
+    map_len: u64,
+    _pad0: [u64; 3],
+    /// head/tail 各占一条 cache line
+    head: AtomicU64,
+    _pad1: [u64; 7],
+    tail: AtomicU64,
+    _pad2: [u64; 7]
+}
+
+#[repr(C)]
+struct SlotHeader {
+    stamp: AtomicU64,
+    /// 只通过 &SlotHeader 访问，所以也要是原子的；先于 stamp 的 Release 写入，晚于它的 Acquire 读取
+    len: AtomicU32,
+    _pad: u32
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PushError {
+    /// 队列满
+    Full,
+    /// 消息超过了创建时指定的 slot_size
+    TooLarge
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PopError {
+    Empty,
+    /// slot 里记录的长度超出了 slot_size，共享内存已经被写坏了
+    Corrupted
+}
+
+/// 放在共享内存里的有界 MPMC 字节消息队列，可以在多个进程之间传递消息。
+pub struct ShmRing {
+    ptr: *mut u8,
+    len: usize,
+    /// 创建/打开时从 Header 里读出并校验过的值。之后不再读 Header 里的这两项，
+    /// 避免别的进程改写它们导致越界访问。
+    slot_count: usize,
+    slot_size: usize,
+    /// 文件映射时保持文件打开；匿名映射时为 None
+    _file: Option<File>
+}
+
+unsafe impl Send for ShmRing {}
+unsafe impl Sync for ShmRing {}
+
+fn slot_stride(slot_size: usize) -> usize {
+    mem::size_of::<SlotHeader>() + slot_size.div_ceil(8) * 8
+}
+
+fn map_len(slot_count: usize, slot_size: usize) -> Option<usize> {
+    slot_stride(slot_size)
+        .checked_mul(slot_count)?
+        .checked_add(mem::size_of::<Header>())
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid shm ring: {}", msg))
+}
+
+unsafe fn mmap(len: usize, fd: libc::c_int) -> io::Result<*mut u8> {
+    let flags = if fd < 0 {
+        libc::MAP_SHARED | libc::MAP_ANONYMOUS
+    } else {
+        libc::MAP_SHARED
+    };
+    let ptr = libc::mmap(ptr::null_mut(), len,
+        libc::PROT_READ | libc::PROT_WRITE, flags, fd, 0);
+    if ptr == libc::MAP_FAILED {
+        Result::Err(io::Error::last_os_error())
+    } else {
+        Result::Ok(ptr as *mut u8)
+    }
+}
+
+impl ShmRing {
+
+    /// 新建（或覆盖）一个文件并在其中初始化 ring。其他进程用 `ShmRing::open` 打开同一路径。
+    /// 放在 /dev/shm 下就是 POSIX 共享内存。
+    pub fn create<P: AsRef<Path>>(path: P, slot_count: usize, slot_size: usize) -> io::Result<Self> {
+        let len = Self::check_params(slot_count, slot_size)?;
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        file.set_len(len as u64)?;
+        let ptr = unsafe { mmap(len, file.as_raw_fd())? };
+        let ring = Self { ptr, len, slot_count, slot_size, _file: Some(file) };
+        ring.init();
+        Result::Ok(ring)
+    }
+
+    /// 匿名共享映射，只能和 fork 出来的子进程共享
+    pub fn anonymous(slot_count: usize, slot_size: usize) -> io::Result<Self> {
+        let len = Self::check_params(slot_count, slot_size)?;
+        let ptr = unsafe { mmap(len, -1)? };
+        let ring = Self { ptr, len, slot_count, slot_size, _file: None };
+        ring.init();
+        Result::Ok(ring)
+    }
+
+    /// 打开另一个进程创建的 ring。
+    ///
+    /// 不信任文件里的任何内容：Header 里的 magic、版本、初始化状态、大小和 head/tail 都会先检查一遍，
+    /// 不合法时返回 `InvalidData`，而不是带着错误的偏移量去访问内存。
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len < mem::size_of::<Header>() {
+            return Result::Err(invalid("file is smaller than the header"));
+        }
+        let ptr = unsafe { mmap(len, file.as_raw_fd())? };
+        let mut ring = Self { ptr, len, slot_count: 0, slot_size: 0, _file: Some(file) };
+        ring.validate()?;
+        Result::Ok(ring)
+    }
+
+    fn check_params(slot_count: usize, slot_size: usize) -> io::Result<usize> {
+        if slot_count == 0 || slot_size == 0 || slot_size > u32::MAX as usize {
+            return Result::Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid slot_count or slot_size"));
+        }
+        map_len(slot_count, slot_size)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "shm ring is too large"))
+    }
+
+    fn init(&self) {
+        let (slot_count, slot_size) = (self.slot_count, self.slot_size);
+        // SAFETY: 新映射的内存只有我们能看到（文件刚被 truncate，其他进程看到的 state 是 0）
+        unsafe {
+            let header = self.ptr as *mut Header;
+            (*header).state.store(STATE_INITIALIZING, Ordering::Relaxed);
+            (*header).magic = MAGIC;
+            (*header).version = VERSION;
+            (*header).slot_count = slot_count as u64;
+            (*header).slot_size = slot_size as u64;
+            (*header).map_len = self.len as u64;
+            (*header).head.store(0, Ordering::Relaxed);
+            (*header).tail.store(0, Ordering::Relaxed);
+            for i in 0..slot_count {
+                self.slot(i).stamp.store(2 * i as u64, Ordering::Relaxed);
+            }
+            (*header).state.store(STATE_READY, Ordering::Release);
+        }
+    }
+
+    fn validate(&mut self) -> io::Result<()> {
+        let header = self.header();
+
+        // 创建者可能还在初始化，稍等一下；超时就认为它已经崩溃了（或者根本不是一个 ring）
+        let start = Instant::now();
+        while header.state.load(Ordering::Acquire) != STATE_READY {
+            if start.elapsed() > INIT_TIMEOUT {
+                if header.magic != MAGIC {
+                    return Result::Err(invalid("bad magic"));
+                }
+                return Result::Err(invalid("header was never fully initialized"));
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        if header.magic != MAGIC {
+            return Result::Err(invalid("bad magic"));
+        }
+        if header.version != VERSION {
+            return Result::Err(invalid("unsupported version"));
+        }
+
+        let slot_count = header.slot_count as usize;
+        let slot_size = header.slot_size as usize;
+        if slot_count == 0 || slot_size == 0 || slot_size > u32::MAX as usize {
+            return Result::Err(invalid("bad slot_count or slot_size"));
+        }
+        if map_len(slot_count, slot_size) != Some(self.len) || header.map_len != self.len as u64 {
+            return Result::Err(invalid("size does not match the header"));
+        }
+
+        let head = header.head.load(Ordering::Acquire);
+        let tail = header.tail.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) > slot_count as u64 {
+            return Result::Err(invalid("head and tail are inconsistent"));
+        }
+
+        self.slot_count = slot_count;
+        self.slot_size = slot_size;
+
+        Result::Ok(())
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*(self.ptr as *const Header) }
+    }
+
+    fn slot(&self, index: usize) -> &SlotHeader {
+        let stride = slot_stride(self.slot_size());
+        unsafe {
+            &*(self.ptr.add(mem::size_of::<Header>() + index * stride) as *const SlotHeader)
+        }
+    }
+
+    fn payload(&self, index: usize) -> *mut u8 {
+        let stride = slot_stride(self.slot_size());
+        unsafe {
+            self.ptr.add(mem::size_of::<Header>() + index * stride + mem::size_of::<SlotHeader>())
+        }
+    }
+
+    pub fn slot_count(&self) -> usize {
+        self.slot_count
+    }
+
+    /// 单条消息的最大长度
+    pub fn slot_size(&self) -> usize {
+        self.slot_size
+    }
+
+    pub fn push(&self, msg: &[u8]) -> Result<(), PushError> {
+        if msg.len() > self.slot_size() {
+            return Result::Err(PushError::TooLarge);
+        }
+        let header = self.header();
+        let cap = self.slot_count() as u64;
+
+        let mut last_tail = header.tail.load(Ordering::Relaxed);
+        let index = loop {
+            let index = (last_tail % cap) as usize;
+            let stamp = self.slot(index).stamp.load(Ordering::Acquire);
+            let diff = stamp.wrapping_sub(last_tail.wrapping_mul(2)) as i64;
+            if diff == 0 {
+                match header.tail.compare_exchange_weak(last_tail, last_tail.wrapping_add(1),
+                    Ordering::Relaxed, Ordering::Relaxed) {
+                    Result::Ok(_) => break index,
+                    Result::Err(x) => last_tail = x
+                }
+            } else if diff < 0 {
+                return Result::Err(PushError::Full);
+            } else {
+                last_tail = header.tail.load(Ordering::Relaxed);
+            }
+        };
+
+        self.slot(index).len.store(msg.len() as u32, Ordering::Relaxed);
+        unsafe { // SAFETY: stamp 保证了同一时间只有一个写者访问这个 slot
+            ptr::copy_nonoverlapping(msg.as_ptr(), self.payload(index), msg.len());
+        }
+        self.slot(index).stamp.store(last_tail.wrapping_mul(2).wrapping_add(1), Ordering::Release);
+        Result::Ok(())
+    }
+
+    pub fn pop(&self) -> Result<Vec<u8>, PopError> {
+        let header = self.header();
+        let cap = self.slot_count() as u64;
+
+        let mut last_head = header.head.load(Ordering::Relaxed);
+        let index = loop {
+            let index = (last_head % cap) as usize;
+            let stamp = self.slot(index).stamp.load(Ordering::Acquire);
+            let diff = stamp.wrapping_sub(last_head.wrapping_mul(2).wrapping_add(1)) as i64;
+            if diff == 0 {
+                match header.head.compare_exchange_weak(last_head, last_head.wrapping_add(1),
+                    Ordering::Relaxed, Ordering::Relaxed) {
+                    Result::Ok(_) => break index,
+                    Result::Err(x) => last_head = x
+                }
+            } else if diff < 0 {
+                return Result::Err(PopError::Empty);
+            } else {
+                last_head = header.head.load(Ordering::Relaxed);
+            }
+        };
+
+        let len = self.slot(index).len.load(Ordering::Relaxed) as usize;
+        let result = if len > self.slot_size() {
+            Result::Err(PopError::Corrupted)
+        } else {
+            let mut msg = vec![0u8; len];
+            unsafe { // SAFETY: 同上，且 len 已经检查过不会越界
+                ptr::copy_nonoverlapping(self.payload(index), msg.as_mut_ptr(), len);
+            }
+            Result::Ok(msg)
+        };
+        // 无论消息是否损坏都要把 slot 还回去，否则整个队列会卡在这里
+        self.slot(index).stamp.store(last_head.wrapping_add(cap).wrapping_mul(2), Ordering::Release);
+        result
+    }
+
+}
+
+impl Drop for ShmRing {
+
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+
+}
@@ -0,0 +1,100 @@
+use std::sync::Mutex;
+
+use crate::queue::Queue;
+
+/// splitmix64，足够用来打乱任务顺序，而且同一个 seed 在任何平台上都给出同样的序列
+pub struct SimRng {
+    state: u64
+}
+
+impl SimRng {
+
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// [0, n) 里的随机数
+    pub fn below(&mut self, n: usize) -> usize {
+        assert!(n > 0);
+        (self.next_u64() % n as u64) as usize
+    }
+
+}
+
+struct SimState<T> {
+    rng: SimRng,
+    /// (任务编号, 任务)。任务编号就是提交顺序，从 0 开始
+    pending: Vec<(u64, T)>,
+    next_id: u64
+}
+
+/// 模拟模式下 ThreadPool 使用的队列。
+///
+/// 不按 FIFO 出队，而是每次用 seed 决定的随机数从所有等待中的任务里挑一个，
+/// 用来模拟多线程下任务被执行的不同顺序。同一个 seed + 同样的提交顺序一定得到同样的执行顺序。
+pub struct SimQueue<T> where T: Send {
+    state: Mutex<SimState<T>>
+}
+
+impl<T> SimQueue<T> where T: Send {
+
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: Mutex::new(SimState {
+                rng: SimRng::new(seed),
+                pending: Vec::new(),
+                next_id: 0
+            })
+        }
+    }
+
+    /// 入队并返回任务编号
+    pub fn push(&self, val: T) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.pending.push((id, val));
+        id
+    }
+
+    /// 随机挑一个任务出队，连同它的编号一起返回
+    pub fn pop_with_id(&self) -> Option<(u64, T)> {
+        let mut state = self.state.lock().unwrap();
+        if state.pending.is_empty() {
+            return None;
+        }
+        let len = state.pending.len();
+        let index = state.rng.below(len);
+        Some(state.pending.remove(index))
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+}
+
+impl<T> Queue<T> for SimQueue<T> where T: Send {
+
+    fn push(&self, val: T) -> Result<(), T> {
+        SimQueue::push(self, val);
+        Result::Ok(())
+    }
+
+    fn pop(&self) -> Result<T, ()> {
+        self.pop_with_id().map(|(_, x)| x).ok_or(())
+    }
+
+}
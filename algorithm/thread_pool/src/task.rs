@@ -0,0 +1,87 @@
+use std::mem::{self, ManuallyDrop, MaybeUninit};
+use std::ptr;
+
+/// 内联存储的大小（以 usize 为单位）。捕获了一两个 Arc 加几个整数的闭包都能放得下。
+const INLINE_WORDS: usize = 4;
+
+type Storage = [MaybeUninit<usize>; INLINE_WORDS];
+
+/// 类型擦除后的 `FnOnce() + Send`，小闭包直接存放在 Task 内部（small buffer optimization）。
+///
+/// 之前每次 queue_task 都要 `Box::new(task)` 一次；现在只有放不下（或者对齐要求超过 usize）的闭包
+/// 才会退回到 Box，此时内联存储里放的是那个 Box 指针。
+pub struct Task {
+    data: Storage,
+    /// 取出闭包并执行
+    call: unsafe fn(*mut Storage),
+    /// 没有执行就被丢弃时，析构闭包
+    drop: unsafe fn(*mut Storage)
+}
+
+/// 闭包是否能直接放进内联存储
+fn fits_inline<F>() -> bool {
+    mem::size_of::<F>() <= mem::size_of::<Storage>()
+        && mem::align_of::<F>() <= mem::align_of::<Storage>()
+}
+
+unsafe fn call_inline<F: FnOnce()>(data: *mut Storage) {
+    let f = ptr::read(data as *mut F);
+    f();
+}
+
+unsafe fn drop_inline<F>(data: *mut Storage) {
+    ptr::drop_in_place(data as *mut F);
+}
+
+unsafe fn call_boxed<F: FnOnce()>(data: *mut Storage) {
+    let f = ptr::read(data as *mut Box<F>);
+    f();
+}
+
+unsafe fn drop_boxed<F>(data: *mut Storage) {
+    ptr::drop_in_place(data as *mut Box<F>);
+}
+
+impl Task {
+
+    pub fn new<F>(f: F) -> Self where F: FnOnce() + Send + 'static {
+        let mut data: Storage = [MaybeUninit::uninit(); INLINE_WORDS];
+        // SAFETY: fits_inline 保证了大小和对齐；Box<F> 是一个指针，总能放下
+        unsafe {
+            if fits_inline::<F>() {
+                ptr::write(data.as_mut_ptr() as *mut F, f);
+                Self { data, call: call_inline::<F>, drop: drop_inline::<F> }
+            } else {
+                ptr::write(data.as_mut_ptr() as *mut Box<F>, Box::new(f));
+                Self { data, call: call_boxed::<F>, drop: drop_boxed::<F> }
+            }
+        }
+    }
+
+    /// 闭包 F 会不会被内联存放（不产生堆分配）
+    pub fn is_inline<F>() -> bool where F: FnOnce() + Send + 'static {
+        fits_inline::<F>()
+    }
+
+    pub fn run(self) {
+        // 闭包在 call 里被 move 出去了，之后无论是否 panic 都不能再对它调用 drop
+        let mut this = ManuallyDrop::new(self);
+        unsafe {
+            (this.call)(&mut this.data);
+        }
+    }
+
+}
+
+/// SAFETY: Task::new 只接受 Send 的闭包
+unsafe impl Send for Task {}
+
+impl Drop for Task {
+
+    fn drop(&mut self) {
+        unsafe {
+            (self.drop)(&mut self.data);
+        }
+    }
+
+}
@@ -0,0 +1,165 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::task::Task;
+use crate::thread_pool::{Job, ThreadPool};
+
+/// 组内一个任务没有正常返回 Ok 的原因
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GroupError<E> {
+    /// 任务自己返回了 Err
+    Failed(E),
+    /// 任务 panic 了
+    Panicked,
+    /// 组被取消时任务还没开始执行，或者被 `OverflowPolicy::Discard` 丢弃了，所以没有执行
+    Cancelled
+}
+
+pub type GroupResult<T, E> = Result<T, GroupError<E>>;
+
+struct GroupState<T, E> {
+    /// 按提交顺序排列，None 表示还没执行完
+    results: Vec<Option<GroupResult<T, E>>>,
+    pending: usize
+}
+
+struct GroupShared<T, E> {
+    state: Mutex<GroupState<T, E>>,
+    all_done: Condvar,
+    cancelled: AtomicBool,
+    cancel_on_error: bool
+}
+
+/// 一组相关的任务：统一提交到 ThreadPool，等待全部完成后按提交顺序拿到每个任务的结果。
+///
+/// 代替手写的计数器 + 等待逻辑。可以设置成任一任务失败（返回 Err 或 panic）时取消组内还没开始的任务。
+pub struct TaskGroup<'a, T, E> where T: Send + 'static, E: Send + 'static {
+    pool: &'a ThreadPool,
+    shared: Arc<GroupShared<T, E>>
+}
+
+impl<'a, T, E> TaskGroup<'a, T, E> where T: Send + 'static, E: Send + 'static {
+
+    pub fn new(pool: &'a ThreadPool) -> Self {
+        Self::with_cancel_on_error(pool, false)
+    }
+
+    /// cancel_on_error 为 true 时，第一个失败的任务会取消组内所有还没开始执行的任务
+    pub fn with_cancel_on_error(pool: &'a ThreadPool, cancel_on_error: bool) -> Self {
+        Self {
+            pool,
+            shared: Arc::new(GroupShared {
+                state: Mutex::new(GroupState {
+                    results: vec![],
+                    pending: 0
+                }),
+                all_done: Condvar::new(),
+                cancelled: AtomicBool::new(false),
+                cancel_on_error
+            })
+        }
+    }
+
+    pub fn spawn<F>(&self, task: F) where F: FnOnce() -> Result<T, E> + Send + 'static {
+        let index = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.results.push(None);
+            state.pending += 1;
+            state.results.len() - 1
+        };
+
+        let shared = self.shared.clone();
+        let task = Task::new(move || {
+            let result = if shared.cancelled.load(Ordering::Acquire) {
+                Result::Err(GroupError::Cancelled)
+            } else {
+                match panic::catch_unwind(AssertUnwindSafe(task)) {
+                    Result::Ok(Result::Ok(x)) => Result::Ok(x),
+                    Result::Ok(Result::Err(e)) => Result::Err(GroupError::Failed(e)),
+                    Result::Err(_) => Result::Err(GroupError::Panicked)
+                }
+            };
+
+            if shared.cancel_on_error && result.is_err() {
+                shared.cancelled.store(true, Ordering::Release);
+            }
+
+            let mut state = shared.state.lock().unwrap();
+            state.results[index] = Some(result);
+            state.pending -= 1;
+            if state.pending == 0 {
+                shared.all_done.notify_all();
+            }
+        });
+
+        // 被 OverflowPolicy::Discard 丢弃的任务不会再执行，直接记为取消
+        if self.pool.submit(Job::new(task, None)).is_err() {
+            let mut state = self.shared.state.lock().unwrap();
+            state.results[index] = Some(Result::Err(GroupError::Cancelled));
+            state.pending -= 1;
+        }
+    }
+
+    /// 取消组内所有还没开始执行的任务，它们的结果会是 `GroupError::Cancelled`
+    pub fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.cancelled.load(Ordering::Acquire)
+    }
+
+    /// 等待组内所有任务结束，按提交顺序返回结果。
+    /// 在暂停的模拟线程池上调用会 panic，因为没有人会执行这些任务
+    pub fn wait(self) -> Vec<GroupResult<T, E>> {
+        match self.wait_until(None) {
+            Result::Ok(results) => results,
+            Result::Err(_) => unreachable!()
+        }
+    }
+
+    /// 和 wait 一样，但最多等待 timeout；超时的话把 TaskGroup 原样还回来，可以之后再等。
+    /// 模拟线程池暂停期间任务不会执行，会一直等到超时
+    pub fn wait_timeout(self, timeout: Duration) -> Result<Vec<GroupResult<T, E>>, Self> {
+        self.wait_until(Some(Instant::now() + timeout))
+    }
+
+    fn wait_until(self, deadline: Option<Instant>) -> Result<Vec<GroupResult<T, E>>, Self> {
+        // 模拟模式下没有工作线程，由等待方自己把任务跑完
+        if self.pool.is_simulated() {
+            while self.shared.state.lock().unwrap().pending > 0 {
+                // 暂停期间 step 不会执行任务：wait 永远等不到结果，wait_timeout 只能等到超时
+                if self.pool.is_paused() {
+                    if deadline.is_none() {
+                        panic!("TaskGroup::wait() would never return: the simulated pool is paused");
+                    }
+                    break;
+                }
+                if self.pool.step().is_none() && !self.pool.is_paused() {
+                    panic!("TaskGroup is waiting for tasks that were never queued");
+                }
+            }
+        }
+
+        let mut state = self.shared.state.lock().unwrap();
+        while state.pending > 0 {
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        drop(state);
+                        return Result::Err(self);
+                    }
+                    state = self.shared.all_done.wait_timeout(state, deadline - now).unwrap().0;
+                },
+                None => state = self.shared.all_done.wait(state).unwrap()
+            }
+        }
+
+        let results = state.results.drain(..).map(|x| x.unwrap()).collect();
+        Result::Ok(results)
+    }
+
+}
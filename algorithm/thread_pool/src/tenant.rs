@@ -0,0 +1,264 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::rate_limit::RateLimiter;
+use crate::thread_pool::Job;
+
+/// 创建 tenant 时的参数
+#[derive(Clone, Debug)]
+pub struct TenantOptions {
+    weight: u32,
+    max_concurrency: Option<usize>,
+    rate_limit: Option<Arc<RateLimiter>>
+}
+
+impl Default for TenantOptions {
+    fn default() -> Self {
+        Self { weight: 1, max_concurrency: None, rate_limit: None }
+    }
+}
+
+impl TenantOptions {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 权重，默认 1。队列都不空的时候，各 tenant 被执行的任务数大致和权重成正比
+    pub fn weight(mut self, weight: u32) -> Self {
+        assert!(weight > 0, "Tenant weight must be positive");
+        self.weight = weight;
+        self
+    }
+
+    /// 同时最多有几个这个 tenant 的任务在执行，默认不限
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        assert!(max_concurrency > 0, "Tenant max_concurrency must be positive");
+        self.max_concurrency = Some(max_concurrency);
+        self
+    }
+
+    /// 限制这个 tenant 开始执行任务的速率，超出的任务留在队列里等待
+    pub fn rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limit = Some(Arc::new(limiter));
+        self
+    }
+
+}
+
+/// 某一时刻一个 tenant 的统计值
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct TenantMetrics {
+    /// 提交给这个 tenant 的任务数
+    pub submitted: u64,
+    /// 执行完的任务数
+    pub completed: u64,
+    /// 还在排队的任务数
+    pub queued: usize,
+    /// 正在执行的任务数
+    pub running: usize
+}
+
+/// 一个 tenant 的任务队列。没有容量上限，提交永远不会失败
+pub(crate) struct TenantQueue {
+    name: String,
+    jobs: Mutex<VecDeque<Job>>,
+    running: AtomicUsize,
+    submitted: AtomicU64,
+    completed: AtomicU64
+}
+
+impl TenantQueue {
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn push(&self, job: Job) {
+        self.jobs.lock().unwrap().push_back(job);
+        self.submitted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 任务执行完（或者 panic）时调用
+    pub fn finish(&self) {
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        self.completed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn metrics(&self) -> TenantMetrics {
+        TenantMetrics {
+            submitted: self.submitted.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+            queued: self.jobs.lock().unwrap().len(),
+            running: self.running.load(Ordering::SeqCst)
+        }
+    }
+
+    /// 没有达到并发上限时出队一个任务，并计入 running
+    fn try_pop(&self, max_concurrency: Option<usize>) -> Option<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.is_empty() {
+            return None;
+        }
+        if let Some(max) = max_concurrency {
+            if self.running.load(Ordering::SeqCst) >= max {
+                return None;
+            }
+        }
+        self.running.fetch_add(1, Ordering::SeqCst);
+        jobs.pop_front()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.lock().unwrap().is_empty()
+    }
+
+}
+
+struct Entry {
+    tenant: Arc<TenantQueue>,
+    weight: u32,
+    max_concurrency: Option<usize>,
+    rate_limit: Option<Arc<RateLimiter>>,
+    deficit: u64
+}
+
+/// 在 ThreadPool 的主队列和各个 tenant 之间按 deficit round-robin 挑选下一个任务。
+///
+/// 主队列（`ThreadPool::queue_task` 提交的任务）相当于一个权重为 1 的默认 tenant。
+/// 每轮轮到某个 tenant 时给它加上等于权重的额度，每执行一个任务花掉 1；
+/// 额度用完或者队列空了就轮到下一个。队列空的 tenant 额度清零，不能把空闲时的额度攒起来。
+pub(crate) struct Scheduler {
+    /// 下标 0 是主队列，不在 entries 里
+    entries: Vec<Entry>,
+    default_deficit: u64,
+    cursor: usize,
+    /// cursor 指向的 tenant 这一轮是否已经加过额度
+    in_turn: bool
+}
+
+/// 挑出来的任务和它所属的 tenant（主队列的任务为 None）
+pub(crate) type Picked = (Job, Option<Arc<TenantQueue>>);
+
+/// 没有挑出任务的原因
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Idle {
+    /// 没有排队的任务（或者都因为并发上限在等正在执行的任务结束）
+    Empty,
+    /// 还有排队的任务，但被 RateLimiter 限住了，过一会儿再来
+    Throttled
+}
+
+impl Scheduler {
+
+    pub fn new() -> Self {
+        Self {
+            entries: vec![],
+            default_deficit: 0,
+            cursor: 0,
+            in_turn: false
+        }
+    }
+
+    /// 注册一个 tenant；同名的 tenant 已经存在时更新它的参数并返回它
+    pub fn register(&mut self, name: &str, options: TenantOptions) -> Arc<TenantQueue> {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.tenant.name == name) {
+            entry.weight = options.weight;
+            entry.max_concurrency = options.max_concurrency;
+            entry.rate_limit = options.rate_limit;
+            return entry.tenant.clone();
+        }
+
+        let tenant = Arc::new(TenantQueue {
+            name: name.to_string(),
+            jobs: Mutex::new(VecDeque::new()),
+            running: AtomicUsize::new(0),
+            submitted: AtomicU64::new(0),
+            completed: AtomicU64::new(0)
+        });
+        self.entries.push(Entry {
+            tenant: tenant.clone(),
+            weight: options.weight,
+            max_concurrency: options.max_concurrency,
+            rate_limit: options.rate_limit,
+            deficit: 0
+        });
+        tenant
+    }
+
+    /// 是否还有 tenant 有排队的任务
+    pub fn has_queued(&self) -> bool {
+        self.entries.iter().any(|e| !e.tenant.is_empty())
+    }
+
+    /// pop_default 从主队列出队一个任务
+    pub fn pick(&mut self, pop_default: &mut dyn FnMut() -> Option<Job>) -> Result<Picked, Idle> {
+        let mut throttled = false;
+        let slots = self.entries.len() + 1;
+        // 每个位置最多访问两次：一次用完上一轮剩下的额度，一次加上新一轮的额度
+        for _ in 0..slots * 2 {
+            if !self.in_turn {
+                self.in_turn = true;
+                match self.cursor {
+                    0 => self.default_deficit += 1,
+                    i => {
+                        let entry = &mut self.entries[i - 1];
+                        entry.deficit += entry.weight as u64;
+                    }
+                }
+            }
+
+            let picked = match self.cursor {
+                0 if self.default_deficit > 0 => match pop_default() {
+                    Some(job) => {
+                        self.default_deficit -= 1;
+                        Some((job, None))
+                    },
+                    None => {
+                        self.default_deficit = 0;
+                        None
+                    }
+                },
+                0 => None,
+                i => {
+                    let entry = &mut self.entries[i - 1];
+                    if entry.deficit == 0 {
+                        None
+                    } else if entry.tenant.is_empty() {
+                        // 队列空了额度清零
+                        entry.deficit = 0;
+                        None
+                    } else if entry.rate_limit.as_ref().is_some_and(|limiter| !limiter.try_acquire()) {
+                        throttled = true;
+                        entry.deficit = entry.deficit.min(entry.weight as u64);
+                        None
+                    } else {
+                        match entry.tenant.try_pop(entry.max_concurrency) {
+                            Some(job) => {
+                                entry.deficit -= 1;
+                                Some((job, Some(entry.tenant.clone())))
+                            },
+                            None => {
+                                if let Some(limiter) = &entry.rate_limit {
+                                    limiter.release();
+                                }
+                                // 达到并发上限时保留这一轮的额度，但不跨轮累积
+                                entry.deficit = entry.deficit.min(entry.weight as u64);
+                                None
+                            }
+                        }
+                    }
+                }
+            };
+
+            if let Some(picked) = picked {
+                return Result::Ok(picked);
+            }
+            self.cursor = (self.cursor + 1) % slots;
+            self.in_turn = false;
+        }
+        Result::Err(if throttled { Idle::Throttled } else { Idle::Empty })
+    }
+
+}
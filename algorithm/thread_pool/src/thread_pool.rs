@@ -0,0 +1,896 @@
+use std::any::Any;
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{self, Ordering, AtomicBool, AtomicU64, AtomicUsize};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::affinity::{self, Affinity, CpuTopology, Placement};
+use crate::broadcast::{Collector, Mailbox, Reply};
+use crate::context::{self, TaskLocals, WorkerInit};
+use crate::events::{Event, EventKind, EventSink};
+use crate::metrics::{Metrics, MetricsSnapshot};
+use crate::queue::Queue;
+use crate::rate_limit::RateLimiter;
+use crate::ring_buffer::RingBuffer;
+use crate::simulation::SimQueue;
+use crate::task::Task;
+use crate::tenant::{Idle, Picked, Scheduler, TenantMetrics, TenantOptions, TenantQueue};
+use crate::watchdog::{OverrunCallback, OverrunReport, Watchdog};
+
+pub type ThreadPoolEntry = Job;
+
+/// 默认任务队列（RingBuffer）的大小
+const DEFAULT_QUEUE_SIZE: usize = 16;
+
+/// 任务队列满时 queue_task 的行为
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OverflowPolicy {
+    /// panic（默认）
+    Panic,
+    /// 阻塞提交方直到队列有空位。注意 pause 期间不会有任务出队，会一直阻塞到 resume
+    Block,
+    /// 直接丢弃新提交的任务
+    Discard
+}
+
+/// 提交任务时的可选参数
+#[derive(Clone, Default, Debug)]
+pub struct TaskOptions {
+    label: Option<String>,
+    deadline: Option<Duration>,
+    locals: TaskLocals
+}
+
+impl TaskOptions {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 任务的名字，会出现在 watchdog 的超时报告里
+    pub fn label<S: Into<String>>(mut self, label: S) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// 任务最多应该运行多久，覆盖 watchdog 的默认阈值。只有启用了 watchdog 的 ThreadPool 才会检查
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// 附带一个 task-local 值，任务里用 `ThreadPool::with_task_local` 读取。每种类型只保留最后设置的一个
+    pub fn local<T>(mut self, value: T) -> Self where T: Send + Sync + 'static {
+        self.locals.insert(value);
+        self
+    }
+
+}
+
+/// 任务队列里的一项：任务本身加上提交时的参数。没有参数时不产生额外的分配
+pub struct Job {
+    task: Task,
+    options: Option<Box<TaskOptions>>,
+    /// 提交时分配的任务编号
+    id: u64
+}
+
+impl Job {
+
+    pub(crate) fn new(task: Task, options: Option<TaskOptions>) -> Self {
+        Self {
+            task,
+            options: options.map(Box::new),
+            id: 0
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.options.as_ref()?.label.as_deref()
+    }
+
+}
+
+/// 工作线程和 ThreadPool 共享的状态
+struct Shared {
+    /// 主队列。开启 numa_local_queues 时每个 NUMA 节点一个，否则只有一个
+    queues: Vec<Arc<dyn Queue<ThreadPoolEntry>>>,
+    /// 每个工作线程优先使用的队列编号
+    worker_queue: Vec<usize>,
+    /// 从工作线程以外提交任务时轮流放进各个队列
+    next_queue: AtomicUsize,
+    destroyed_flag: AtomicBool,
+    paused: AtomicBool,
+    /// 正在检查 paused 或执行任务的工作线程数。pause 等它归零，确保之后不会有任务开始执行
+    running: AtomicUsize,
+    /// 只用来配合 state_changed 和 space_available 等待，本身不保护数据
+    lock: Mutex<()>,
+    /// paused/destroyed_flag/running 变化时通知
+    state_changed: Condvar,
+    /// 主队列有任务出队、并且有提交方在等空位时通知（OverflowPolicy::Block）
+    space_available: Condvar,
+    /// 因为 OverflowPolicy::Block 在等空位的提交方数量。为 0 时出队不用碰锁
+    blocked_submitters: AtomicUsize,
+    metrics: Metrics,
+    watchdog: Option<Watchdog>,
+    /// 每个工作线程一个，放 broadcast 的任务
+    mailboxes: Vec<Mailbox>,
+    /// 有 tenant 时由它在主队列和各个 tenant 之间挑选任务
+    scheduler: Mutex<Scheduler>,
+    /// 没有 tenant 时直接从主队列出队，不用经过 scheduler 的锁
+    has_tenants: AtomicBool,
+    /// 主队列里排队的任务数。Queue 本身不提供，限速时用来区分"队列空了"和"被限住了"
+    queued: AtomicUsize,
+    rate_limit: Option<Arc<RateLimiter>>,
+    events: Option<Arc<dyn EventSink>>,
+    /// 事件时间戳的起点
+    created: Instant,
+    next_task_id: AtomicU64,
+    /// PoolShutdown 只发一次（join 之后还会 drop）
+    shutdown_reported: AtomicBool
+}
+
+impl Shared {
+
+    fn notify_all(&self) {
+        let _guard = self.lock.lock().unwrap();
+        self.state_changed.notify_all();
+    }
+
+    /// 主队列出队之后调用。先出队再检查 blocked_submitters，和 submit 里"先登记再重试入队"配对，
+    /// 中间都隔着 SeqCst fence，所以两边至少有一方能看到对方，不会丢失唤醒
+    fn notify_space(&self) {
+        atomic::fence(Ordering::SeqCst);
+        if self.blocked_submitters.load(Ordering::SeqCst) > 0 {
+            let _guard = self.lock.lock().unwrap();
+            self.space_available.notify_all();
+        }
+    }
+
+    fn emit(&self, kind: EventKind, timestamp: Duration, task_id: Option<u64>, worker: Option<usize>, label: Option<&str>) {
+        if let Some(events) = &self.events {
+            events.event(&Event { kind, timestamp, task_id, worker, label });
+        }
+    }
+
+    fn emit_now(&self, kind: EventKind, task_id: Option<u64>, worker: Option<usize>, label: Option<&str>) {
+        if self.events.is_some() {
+            self.emit(kind, self.created.elapsed(), task_id, worker, label);
+        }
+    }
+
+    /// 给任务分配编号，返回提交时刻；入队成功后用它发 TaskSubmitted，这样时间戳一定早于 TaskStarted
+    fn prepare_submit(&self, job: &mut Job) -> Duration {
+        job.id = self.next_task_id.fetch_add(1, Ordering::Relaxed);
+        if self.events.is_some() { self.created.elapsed() } else { Duration::from_secs(0) }
+    }
+
+    fn report_shutdown(&self) {
+        if !self.shutdown_reported.swap(true, Ordering::SeqCst) {
+            self.emit_now(EventKind::PoolShutdown, None, None, None);
+        }
+    }
+
+    /// 先从 worker 自己的队列出队，空了再从其他队列拿，保证所有任务最终都会被执行
+    fn pop_default(&self, worker: usize) -> Option<Job> {
+        let home = self.worker_queue.get(worker).copied().unwrap_or(0);
+        let count = self.queues.len();
+        let job = (0..count)
+            .find_map(|i| self.queues[(home + i) % count].pop().ok())?;
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        self.notify_space();
+        Some(job)
+    }
+
+    fn next_job(&self, worker: usize) -> Result<Picked, Idle> {
+        // 先拿到令牌再出队，没用上再还回去，避免多个工作线程同时看到有令牌而超出限制
+        if let Some(limiter) = &self.rate_limit {
+            if !limiter.try_acquire() {
+                let has_queued = self.queued.load(Ordering::SeqCst) > 0
+                    || (self.has_tenants.load(Ordering::Acquire) && self.scheduler.lock().unwrap().has_queued());
+                return Result::Err(if has_queued { Idle::Throttled } else { Idle::Empty });
+            }
+        }
+
+        let picked = if self.has_tenants.load(Ordering::Acquire) {
+            self.scheduler.lock().unwrap().pick(&mut || self.pop_default(worker))
+        } else {
+            self.pop_default(worker).map(|job| (job, None)).ok_or(Idle::Empty)
+        };
+        if picked.is_err() {
+            if let Some(limiter) = &self.rate_limit {
+                limiter.release();
+            }
+        }
+        picked
+    }
+
+    /// 在当前线程上执行一个任务；worker 是执行它的工作线程编号（模拟模式下是 0）
+    fn run_job(&self, worker: usize, job: Job) {
+        let id = job.id;
+        // 只有需要发事件时才复制 label
+        let event_label = match &self.events {
+            Some(_) => job.label().map(|s| s.to_string()),
+            None => None
+        };
+        self.emit_now(EventKind::TaskStarted, Some(id), Some(worker), event_label.as_deref());
+
+        let task = job.task;
+        let (label, deadline, locals) = match job.options {
+            Some(options) => {
+                let options = *options;
+                (options.label, options.deadline, options.locals)
+            },
+            None => (None, None, TaskLocals::default())
+        };
+        let _locals = context::enter_task(locals);
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.begin(worker, label, deadline);
+        }
+        let result = panic::catch_unwind(AssertUnwindSafe(|| task.run()));
+        // panic 的任务也要清掉 watchdog 里的记录，否则它会一直占着这个工作线程的槽位
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.end(worker, &self.metrics);
+        }
+        if let Result::Err(payload) = result {
+            self.emit_now(EventKind::TaskPanicked, Some(id), Some(worker), event_label.as_deref());
+            panic::resume_unwind(payload);
+        }
+        self.emit_now(EventKind::TaskFinished, Some(id), Some(worker), event_label.as_deref());
+        self.metrics.record_completed();
+    }
+
+}
+
+thread_local! {
+    /// 当前线程所属的 ThreadPool（的 Shared 地址），用来发现在任务里调用 pause 这类会死锁的用法
+    static CURRENT_POOL: Cell<*const Shared> = const { Cell::new(ptr::null()) };
+}
+
+/// 模拟模式下 0 号工作线程的身份。drop 时把工作线程状态放回 sim_worker_state，任务 panic 也不会把它弄丢
+struct SimWorker<'a> {
+    slot: &'a Mutex<Option<Option<Box<dyn Any + Send>>>>,
+    scope: Option<context::WorkerScope>
+}
+
+impl Drop for SimWorker<'_> {
+
+    fn drop(&mut self) {
+        if let Some(scope) = self.scope.take() {
+            let state = scope.exit();
+            // 可能正在 unwind，不能再因为锁中毒 panic
+            *self.slot.lock().unwrap_or_else(PoisonError::into_inner) = Some(state);
+        }
+    }
+
+}
+
+pub struct ThreadPool {
+    shared: Arc<Shared>,
+    child_threads: Vec<thread::JoinHandle<()>>,
+    watchdog_thread: Option<thread::JoinHandle<()>>,
+    overflow_policy: OverflowPolicy,
+    /// 模拟模式下的任务队列；不为 None 时没有任何工作线程，任务都在调用 step 的线程上执行
+    simulation: Option<Arc<SimQueue<ThreadPoolEntry>>>,
+    /// 模拟模式下 0 号工作线程的状态。step 执行任务期间被取出（外层为 None），所以任务里嵌套的 step 不会再切换一次
+    sim_worker_state: Mutex<Option<Option<Box<dyn Any + Send>>>>
+}
+
+/// 用于配置 ThreadPool 的参数。不配置队列时使用固定大小的 RingBuffer。
+pub struct ThreadPoolBuilder {
+    thread_count: usize,
+    queue: Option<Arc<dyn Queue<ThreadPoolEntry>>>,
+    overflow_policy: OverflowPolicy,
+    watchdog: bool,
+    watchdog_threshold: Option<Duration>,
+    on_overrun: Option<OverrunCallback>,
+    worker_init: Option<WorkerInit>,
+    rate_limit: Option<Arc<RateLimiter>>,
+    affinity: Option<Affinity>,
+    numa_local_queues: bool,
+    events: Option<Arc<dyn EventSink>>,
+    simulation_seed: Option<u64>
+}
+
+impl ThreadPoolBuilder {
+
+    pub fn new(thread_count: usize) -> Self {
+        Self {
+            thread_count,
+            queue: None,
+            overflow_policy: OverflowPolicy::Panic,
+            watchdog: false,
+            watchdog_threshold: None,
+            on_overrun: None,
+            worker_init: None,
+            rate_limit: None,
+            affinity: None,
+            numa_local_queues: false,
+            events: None,
+            simulation_seed: None
+        }
+    }
+
+    /// 指定任务队列，比如用 `SegQueue` 来接受任意数量的排队任务
+    pub fn queue<Q>(mut self, queue: Q) -> Self where Q: Queue<ThreadPoolEntry> + 'static {
+        self.queue = Some(Arc::new(queue));
+        self
+    }
+
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// 启动一个 watchdog 线程，报告运行时间超过 threshold 的任务（带 deadline 的任务以自己的 deadline 为准）。
+    /// 报告里有任务的 label 和执行它的工作线程编号，默认打印到 stderr，可以用 `on_overrun` 换成自己的处理。
+    pub fn watchdog(mut self, threshold: Duration) -> Self {
+        self.watchdog = true;
+        self.watchdog_threshold = Some(threshold);
+        self
+    }
+
+    /// 只检查设置了 deadline 的任务，不设默认阈值
+    pub fn watchdog_deadlines_only(mut self) -> Self {
+        self.watchdog = true;
+        self.watchdog_threshold = None;
+        self
+    }
+
+    /// watchdog 发现任务超时时的回调，在 watchdog 线程上执行
+    pub fn on_overrun<F>(mut self, callback: F) -> Self where F: Fn(&OverrunReport) + Send + Sync + 'static {
+        self.on_overrun = Some(Arc::new(callback));
+        self
+    }
+
+    /// 每个工作线程启动时在该线程上调用一次 init(工作线程编号)，返回值作为这个工作线程的状态，
+    /// 任务里通过 `ThreadPool::with_worker_state` 访问。适合放每个工作线程自己的缓冲区、分片计数器等。
+    /// 模拟模式下只有一个 0 号工作线程，init 在 build 时调用。
+    pub fn worker_init<S, F>(mut self, init: F) -> Self where S: Send + 'static, F: Fn(usize) -> S + Send + Sync + 'static {
+        self.worker_init = Some(Arc::new(move |index| Box::new(init(index)) as Box<dyn Any + Send>));
+        self
+    }
+
+    /// 限制整个 ThreadPool 开始执行任务的速率（包括各个 tenant 的任务），超出的任务留在队列里等待。
+    /// 模拟模式下 step 不受限速影响
+    pub fn rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limit = Some(Arc::new(limiter));
+        self
+    }
+
+    /// 把工作线程绑定到指定的核上（仅 Linux）。绑定在工作线程启动时、worker_init 之前完成，失败时打印警告并继续运行
+    pub fn affinity(mut self, affinity: Affinity) -> Self {
+        self.affinity = Some(affinity);
+        self
+    }
+
+    /// 每个 NUMA 节点使用一个单独的任务队列（默认的 RingBuffer），工作线程优先执行自己节点队列里的任务，
+    /// 空了再去其他节点的队列里拿。工作线程提交的任务放进自己节点的队列，其他线程提交的任务轮流放进各个队列。
+    ///
+    /// 没有用 `affinity` 绑定的工作线程会依次分配到各个节点，并绑定到该节点的所有核上。
+    /// 读取不到 NUMA 拓扑时退回到单个队列。不能和 `queue` 一起使用。
+    pub fn numa_local_queues(mut self, enabled: bool) -> Self {
+        self.numa_local_queues = enabled;
+        self
+    }
+
+    /// 把任务和工作线程的事件（见 `EventKind`）发给 sink，比如用 `ChromeTraceSink` 记录下来导出成时间线
+    pub fn event_sink<S>(mut self, sink: Arc<S>) -> Self where S: EventSink + 'static {
+        self.events = Some(sink);
+        self
+    }
+
+    /// 单线程的确定性模拟模式，用来复现依赖执行顺序的 bug。
+    ///
+    /// 不创建工作线程，也忽略 `queue` 的配置；任务只在调用 `ThreadPool::step`/`run_until_idle`/`join` 时
+    /// 在当前线程上执行，执行顺序由 seed 决定。同一个 seed 总能重放出同样的顺序。
+    pub fn simulated(mut self, seed: u64) -> Self {
+        self.simulation_seed = Some(seed);
+        self
+    }
+
+    pub fn build(self) -> ThreadPool {
+        let simulation = self.simulation_seed.map(|seed| Arc::new(SimQueue::new(seed)));
+        let placement = if simulation.is_some() {
+            Placement::plan(0, None, false, CpuTopology::read)
+        } else {
+            assert!(!(self.numa_local_queues && self.queue.is_some()),
+                "numa_local_queues cannot be combined with a custom queue");
+            Placement::plan(self.thread_count, self.affinity.as_ref(), self.numa_local_queues, CpuTopology::read)
+        };
+        let queues: Vec<Arc<dyn Queue<ThreadPoolEntry>>> = match (&simulation, self.queue) {
+            (Some(sim), _) => vec![sim.clone()],
+            (None, Some(queue)) => vec![queue],
+            (None, None) => (0..placement.queue_count)
+                .map(|_| Arc::new(RingBuffer::<ThreadPoolEntry>::new(DEFAULT_QUEUE_SIZE)) as Arc<dyn Queue<ThreadPoolEntry>>)
+                .collect()
+        };
+        let thread_count = placement.thread_count;
+        let watchdog = if self.watchdog {
+            Some(Watchdog::new(thread_count, self.watchdog_threshold, self.on_overrun))
+        } else {
+            None
+        };
+        let shared = Arc::new(Shared {
+            queues,
+            worker_queue: placement.worker_queue,
+            next_queue: AtomicUsize::new(0),
+            destroyed_flag: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            running: AtomicUsize::new(0),
+            lock: Mutex::new(()),
+            state_changed: Condvar::new(),
+            space_available: Condvar::new(),
+            blocked_submitters: AtomicUsize::new(0),
+            metrics: Metrics::default(),
+            watchdog,
+            mailboxes: (0..thread_count).map(|_| Mailbox::new()).collect(),
+            scheduler: Mutex::new(Scheduler::new()),
+            has_tenants: AtomicBool::new(false),
+            queued: AtomicUsize::new(0),
+            rate_limit: self.rate_limit,
+            events: self.events,
+            created: Instant::now(),
+            next_task_id: AtomicU64::new(0),
+            shutdown_reported: AtomicBool::new(false)
+        });
+
+        let mut child_threads = vec![];
+        for (i, cpus) in placement.worker_cpus.into_iter().enumerate() {
+            let shared = shared.clone();
+            let worker_init = self.worker_init.clone();
+            let join_handle = thread::spawn(move || {
+                if let Some(cpus) = cpus {
+                    if let Result::Err(e) = affinity::pin_current_thread(&cpus) {
+                        eprintln!("[affinity] failed to pin worker #{} to CPUs {:?}: {}", i, cpus, e);
+                    }
+                }
+                CURRENT_POOL.with(|p| p.set(Arc::as_ptr(&shared)));
+                let _worker = context::enter_worker(i, worker_init.map(|init| init(i)));
+                let _mailbox = CloseOnExit(&shared.mailboxes[i]);
+                worker_loop(&shared, i);
+            });
+
+            child_threads.push(join_handle);
+        }
+
+        // 模拟模式下任务结束时由 run_job 检查超时，不需要 watchdog 线程
+        let watchdog_thread = match &shared.watchdog {
+            Some(_) if thread_count > 0 => {
+                let shared = shared.clone();
+                Some(thread::spawn(move || watchdog_loop(&shared)))
+            },
+            _ => None
+        };
+
+        let sim_worker_state = match (&simulation, &self.worker_init) {
+            (Some(_), Some(init)) => Some(Some(init(0))),
+            _ => Some(None)
+        };
+
+        ThreadPool {
+            shared,
+            child_threads,
+            watchdog_thread,
+            overflow_policy: self.overflow_policy,
+            simulation,
+            sim_worker_state: Mutex::new(sim_worker_state)
+        }
+    }
+
+}
+
+/// 离开 running 状态时把计数减回去并通知 pause（包括任务 panic 导致工作线程退出的情况，不然 pause 会永远等下去）
+struct RunningGuard<'a>(&'a Shared);
+
+impl Drop for RunningGuard<'_> {
+
+    fn drop(&mut self) {
+        self.0.running.fetch_sub(1, Ordering::SeqCst);
+        if self.0.paused.load(Ordering::SeqCst) {
+            self.0.notify_all();
+        }
+    }
+
+}
+
+/// 工作线程退出（包括任务 panic 导致的退出）时关闭它的 Mailbox，之后的 broadcast 不会再等它
+struct CloseOnExit<'a>(&'a Mailbox);
+
+impl Drop for CloseOnExit<'_> {
+
+    fn drop(&mut self) {
+        self.0.close();
+    }
+
+}
+
+/// tenant 的任务结束（包括 panic）时把它从 running 里减掉
+struct FinishTenant(Option<Arc<TenantQueue>>);
+
+impl Drop for FinishTenant {
+
+    fn drop(&mut self) {
+        if let Some(tenant) = &self.0 {
+            tenant.finish();
+        }
+    }
+
+}
+
+fn worker_loop(shared: &Shared, index: usize) {
+    let mailbox = &shared.mailboxes[index];
+    // 只在状态变化时发 WorkerParked/WorkerWoken，空闲时的每次轮询不发
+    let mut parked = false;
+    let set_parked = |parked: &mut bool, now: bool| {
+        if *parked != now {
+            *parked = now;
+            let kind = if now { EventKind::WorkerParked } else { EventKind::WorkerWoken };
+            shared.emit_now(kind, None, Some(index), None);
+        }
+    };
+    loop {
+        // broadcast 的任务优先于队列里的普通任务，也不受 pause 影响
+        if let Some(task) = mailbox.pop() {
+            set_parked(&mut parked, false);
+            task.run();
+            continue;
+        }
+
+        // 先登记再检查 paused（都是 SeqCst），和 pause 里的"先设置 paused 再检查 running"配对：
+        // 两边至少有一方能看到对方，所以 pause 返回之后不会再有任务开始执行
+        shared.running.fetch_add(1, Ordering::SeqCst);
+        let running = RunningGuard(shared);
+
+        if shared.paused.load(Ordering::SeqCst) {
+            drop(running);
+            set_parked(&mut parked, true);
+
+            let mut guard = shared.lock.lock().unwrap();
+            while shared.paused.load(Ordering::SeqCst) && !shared.destroyed_flag.load(Ordering::SeqCst) && mailbox.is_empty() {
+                guard = shared.state_changed.wait(guard).unwrap();
+            }
+            drop(guard);
+
+            if shared.destroyed_flag.load(Ordering::SeqCst) {
+                break
+            }
+            continue;
+        }
+
+        match shared.next_job(index) {
+            Result::Ok((job, tenant)) => {
+                set_parked(&mut parked, false);
+                let _tenant = FinishTenant(tenant);
+                shared.run_job(index, job);
+            },
+            // 被限速的任务还在排队，即使已经 join 也要等它们执行完
+            Result::Err(Idle::Throttled) => {
+                drop(running);
+                set_parked(&mut parked, true);
+                thread::yield_now()
+            },
+            Result::Err(Idle::Empty) => {
+                drop(running);
+                set_parked(&mut parked, true);
+                if shared.destroyed_flag.load(Ordering::Relaxed) {
+                    break
+                } else {
+                    thread::yield_now()
+                }
+            }
+        }
+    }
+}
+
+fn watchdog_loop(shared: &Shared) {
+    let watchdog = shared.watchdog.as_ref().unwrap();
+    let interval = watchdog.interval();
+    let mut guard = shared.lock.lock().unwrap();
+    while !shared.destroyed_flag.load(Ordering::SeqCst) {
+        drop(guard);
+        watchdog.scan(&shared.metrics);
+        guard = shared.lock.lock().unwrap();
+        if shared.destroyed_flag.load(Ordering::SeqCst) {
+            break;
+        }
+        guard = shared.state_changed.wait_timeout(guard, interval).unwrap().0;
+    }
+}
+
+impl ThreadPool {
+
+    pub fn new(thread_count: usize) -> Self {
+        ThreadPoolBuilder::new(thread_count).build()
+    }
+
+    pub fn builder(thread_count: usize) -> ThreadPoolBuilder {
+        ThreadPoolBuilder::new(thread_count)
+    }
+
+    pub fn queue_task<F>(&self, task: F) where F: FnOnce() + Send + 'static {
+        let _ = self.submit(Job::new(Task::new(task), None));
+    }
+
+    /// 带参数（label、deadline 等）提交任务
+    pub fn queue_task_with<F>(&self, options: TaskOptions, task: F) where F: FnOnce() + Send + 'static {
+        let _ = self.submit(Job::new(Task::new(task), Some(options)));
+    }
+
+    /// 按 OverflowPolicy 把任务放进队列；任务被丢弃时把它还回来
+    pub(crate) fn submit(&self, mut task: Job) -> Result<(), Job> {
+        let submitted_at = self.shared.prepare_submit(&mut task);
+        let id = task.id;
+        let label = match &self.shared.events {
+            Some(_) => task.label().map(|s| s.to_string()),
+            None => None
+        };
+        // OverflowPolicy::Block 登记为等待方之后一直持有锁，只在 wait 时释放
+        let mut blocked: Option<MutexGuard<()>> = None;
+        loop {
+            // 先计数再入队，不然工作线程可能在计数之前就把任务取走了
+            self.shared.queued.fetch_add(1, Ordering::SeqCst);
+            match self.shared.queues[self.queue_for_submit()].push(task) {
+                Result::Ok(_) => {
+                    if let Some(guard) = blocked.take() {
+                        self.shared.blocked_submitters.fetch_sub(1, Ordering::SeqCst);
+                        drop(guard);
+                    }
+                    self.shared.metrics.record_submitted();
+                    self.shared.emit(EventKind::TaskSubmitted, submitted_at, Some(id), None, label.as_deref());
+                    return Result::Ok(());
+                },
+                Result::Err(t) => {
+                    self.shared.queued.fetch_sub(1, Ordering::SeqCst);
+                    match self.overflow_policy {
+                        OverflowPolicy::Panic => panic!("Thread pending queue is full"),
+                        OverflowPolicy::Discard => {
+                            self.shared.metrics.record_discarded();
+                            return Result::Err(t);
+                        },
+                        OverflowPolicy::Block => {
+                            task = t;
+                            blocked = Some(match blocked.take() {
+                                // 第一次满：登记之后不等待，先回去再试一次入队，见 Shared::notify_space
+                                None => {
+                                    let guard = self.shared.lock.lock().unwrap();
+                                    self.shared.blocked_submitters.fetch_add(1, Ordering::SeqCst);
+                                    atomic::fence(Ordering::SeqCst);
+                                    guard
+                                },
+                                Some(guard) => self.shared.space_available.wait(guard).unwrap()
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// 工作线程提交到自己的队列，其他线程轮流提交到各个队列
+    fn queue_for_submit(&self) -> usize {
+        let count = self.shared.queues.len();
+        if count == 1 {
+            return 0;
+        }
+        if CURRENT_POOL.with(|p| p.get()) == Arc::as_ptr(&self.shared) {
+            if let Some(worker) = context::current_worker() {
+                return self.shared.worker_queue[worker];
+            }
+        }
+        self.shared.next_queue.fetch_add(1, Ordering::Relaxed) % count
+    }
+
+    /// 获取名为 name 的 tenant 的句柄，不存在时创建；已经存在时用 options 更新它的权重和并发上限。
+    ///
+    /// 每个 tenant 有自己的无界队列，工作线程在主队列和各个 tenant 之间按权重做 deficit round-robin，
+    /// 所以一个 tenant 提交大量任务不会饿死其他 tenant。tenant 的队列没有容量上限，不受 OverflowPolicy 影响。
+    /// 模拟模式下不支持 tenant。
+    pub fn tenant(&self, name: &str, options: TenantOptions) -> Tenant {
+        assert!(!self.is_simulated(), "Tenants are not supported on a simulated ThreadPool");
+        let queue = self.shared.scheduler.lock().unwrap().register(name, options);
+        self.shared.has_tenants.store(true, Ordering::Release);
+        Tenant {
+            queue,
+            shared: self.shared.clone()
+        }
+    }
+
+    /// 在每个工作线程上各执行一次 f(工作线程编号)，等全部执行完后按工作线程编号返回结果。
+    ///
+    /// 用来做每个线程都要做的准备或收尾工作，比如预热 thread-local 缓存、刷新每个线程自己的缓冲区。
+    /// f 会插到各个工作线程的下一个任务之前执行，不用等队列里已有的任务；暂停期间也会执行。
+    /// 已经退出的工作线程（任务 panic 导致）不会执行，也就没有它的结果。
+    /// f 在某个工作线程上 panic 时，等所有工作线程执行完后在调用方重新 panic。
+    /// 不能在本线程池的任务里调用，否则会等待自己而死锁。模拟模式下在当前线程上以 0 号工作线程的身份执行一次。
+    pub fn broadcast<R, F>(&self, f: F) -> Vec<R> where R: Send + 'static, F: Fn(usize) -> R + Send + Sync + 'static {
+        if CURRENT_POOL.with(|p| p.get()) == Arc::as_ptr(&self.shared) {
+            panic!("ThreadPool::broadcast() called from one of its own tasks would deadlock");
+        }
+        if self.is_simulated() {
+            return vec![self.run_on_sim_worker(|| f(0))];
+        }
+
+        let f = Arc::new(f);
+        let collector = Collector::new(self.shared.mailboxes.len());
+        for (i, mailbox) in self.shared.mailboxes.iter().enumerate() {
+            let f = f.clone();
+            let reply = Reply::new(collector.clone(), i);
+            let task = Task::new(move || {
+                reply.send(panic::catch_unwind(AssertUnwindSafe(|| f(i))));
+            });
+            // 发送失败时任务连同 Reply 一起被丢弃，Collector 不会等这个工作线程
+            let _ = mailbox.send(task);
+        }
+        self.shared.notify_all();
+
+        collector.wait().into_iter()
+            .map(|result| result.unwrap_or_else(|payload| panic::resume_unwind(payload)))
+            .collect()
+    }
+
+    /// 暂停：正在执行的任务会继续执行完，之后工作线程不再从队列里取新任务，直到 resume。
+    ///
+    /// 会阻塞到所有正在执行的任务结束为止，返回之后可以确定没有任务在运行。
+    /// 暂停期间 queue_task 仍然会把任务放进队列（队列满时按 OverflowPolicy 处理）。
+    /// 不能在本线程池的任务里调用，否则会等待自己而死锁。
+    pub fn pause(&self) {
+        if CURRENT_POOL.with(|p| p.get()) == Arc::as_ptr(&self.shared) {
+            panic!("ThreadPool::pause() called from one of its own tasks would deadlock");
+        }
+
+        self.shared.paused.store(true, Ordering::SeqCst);
+        let mut guard = self.shared.lock.lock().unwrap();
+        while self.shared.running.load(Ordering::SeqCst) > 0 {
+            guard = self.shared.state_changed.wait(guard).unwrap();
+        }
+    }
+
+    pub fn resume(&self) {
+        self.shared.paused.store(false, Ordering::SeqCst);
+        self.shared.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.paused.load(Ordering::SeqCst)
+    }
+
+    /// 当前线程在所属 ThreadPool 里的工作线程编号（0..thread_count）；不在工作线程上时返回 None。
+    /// 模拟模式下 step 执行的任务看到的编号总是 0
+    pub fn current_worker() -> Option<usize> {
+        context::current_worker()
+    }
+
+    /// 对当前工作线程的状态（见 `ThreadPoolBuilder::worker_init`）执行 f。
+    /// 不在工作线程上、没有设置 worker_init 或者类型 S 不对时返回 None；在 f 里嵌套调用也会得到 None
+    pub fn with_worker_state<S, R, F>(f: F) -> Option<R> where S: 'static, F: FnOnce(&mut S) -> R {
+        context::with_worker_state(f)
+    }
+
+    /// 读取当前任务提交时通过 `TaskOptions::local` 附带的 T 类型的值；没有时返回 None
+    pub fn with_task_local<T, R, F>(f: F) -> Option<R> where T: 'static, F: FnOnce(&T) -> R {
+        context::with_task_local(f)
+    }
+
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.shared.metrics.snapshot()
+    }
+
+    pub fn is_simulated(&self) -> bool {
+        self.simulation.is_some()
+    }
+
+    /// 模拟模式下执行一个任务，返回它的编号（提交顺序，从 0 开始）；没有任务或者已经暂停时返回 None
+    pub fn step(&self) -> Option<u64> {
+        let sim = self.simulation.as_ref().expect("step() is only available on a simulated ThreadPool");
+        if self.is_paused() {
+            return None;
+        }
+        let (id, job) = sim.pop_with_id()?;
+        self.shared.notify_space();
+        self.run_on_sim_worker(|| self.shared.run_job(0, job));
+        Some(id)
+    }
+
+    /// 模拟模式下以 0 号工作线程的身份在当前线程上执行 f
+    fn run_on_sim_worker<R, F>(&self, f: F) -> R where F: FnOnce() -> R {
+        let state = self.sim_worker_state.lock().unwrap().take();
+        match state {
+            Some(state) => {
+                let _worker = SimWorker {
+                    slot: &self.sim_worker_state,
+                    scope: Some(context::enter_worker(0, state))
+                };
+                f()
+            },
+            // 在外层 step 执行的任务里，已经是 0 号工作线程了
+            None => f()
+        }
+    }
+
+    /// 模拟模式下一直执行到队列为空（包括任务执行过程中新提交的任务），按执行顺序返回任务编号
+    pub fn run_until_idle(&self) -> Vec<u64> {
+        let mut order = vec![];
+        while let Some(id) = self.step() {
+            order.push(id);
+        }
+        order
+    }
+
+    /// join 会等待队列里所有任务执行完；如果处于暂停状态，会先 resume。
+    ///
+    /// join实现方式不是很优雅，因为实现了Drop所以无法将成员变量move out，
+    /// 所以只能用swap vec的方式拿到所有thread handle。
+    pub fn join(mut self) {
+        self.resume();
+        if self.is_simulated() {
+            self.run_until_idle();
+        }
+        self.shared.destroyed_flag.store(true, Ordering::SeqCst);
+        self.shared.notify_all();
+
+        let mut v = Vec::new();
+        std::mem::swap(&mut v, &mut self.child_threads);
+        for handle in v {
+            handle.join().expect("Failed to join thread");
+        }
+        if let Some(handle) = self.watchdog_thread.take() {
+            handle.join().expect("Failed to join watchdog thread");
+        }
+        self.shared.report_shutdown();
+    }
+
+}
+
+impl Drop for ThreadPool {
+
+    fn drop(&mut self) {
+        self.shared.destroyed_flag.store(true, Ordering::SeqCst);
+        self.shared.notify_all();
+        self.shared.report_shutdown();
+    }
+
+}
+
+/// ThreadPool 里一个 tenant 的句柄，通过 `ThreadPool::tenant` 获取，可以 clone 给不同的提交方
+#[derive(Clone)]
+pub struct Tenant {
+    queue: Arc<TenantQueue>,
+    shared: Arc<Shared>
+}
+
+impl Tenant {
+
+    pub fn name(&self) -> &str {
+        self.queue.name()
+    }
+
+    pub fn queue_task<F>(&self, task: F) where F: FnOnce() + Send + 'static {
+        self.submit(Job::new(Task::new(task), None));
+    }
+
+    pub fn queue_task_with<F>(&self, options: TaskOptions, task: F) where F: FnOnce() + Send + 'static {
+        self.submit(Job::new(Task::new(task), Some(options)));
+    }
+
+    fn submit(&self, mut job: Job) {
+        let submitted_at = self.shared.prepare_submit(&mut job);
+        let id = job.id;
+        let label = match &self.shared.events {
+            Some(_) => job.label().map(|s| s.to_string()),
+            None => None
+        };
+        self.queue.push(job);
+        self.shared.metrics.record_submitted();
+        self.shared.emit(EventKind::TaskSubmitted, submitted_at, Some(id), None, label.as_deref());
+    }
+
+    /// 这个 tenant 自己的统计；ThreadPool::metrics 里也包含了 tenant 的任务
+    pub fn metrics(&self) -> TenantMetrics {
+        self.queue.metrics()
+    }
+
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::metrics::Metrics;
+
+/// 一个任务运行超时的报告
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OverrunReport {
+    /// 执行这个任务的工作线程编号
+    pub worker: usize,
+    /// 提交时通过 `TaskOptions::label` 设置的标签
+    pub label: Option<String>,
+    /// 发现超时的时候已经运行了多久
+    pub elapsed: Duration,
+    /// 这个任务的时限：任务自己的 deadline，或者 watchdog 的默认阈值
+    pub limit: Duration
+}
+
+impl fmt::Display for OverrunReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "task {} on worker #{} has been running for {:?} (limit {:?})",
+            self.label.as_deref().unwrap_or("<unlabeled>"), self.worker, self.elapsed, self.limit)
+    }
+}
+
+pub(crate) type OverrunCallback = Arc<dyn Fn(&OverrunReport) + Send + Sync + 'static>;
+
+struct RunningTask {
+    label: Option<String>,
+    started: Instant,
+    limit: Option<Duration>,
+    /// 每个任务只报告一次
+    reported: bool
+}
+
+/// 记录每个工作线程正在执行的任务，由 watchdog 线程定期检查。
+pub(crate) struct Watchdog {
+    /// 没有设置 deadline 的任务使用的默认阈值
+    threshold: Option<Duration>,
+    callback: Option<OverrunCallback>,
+    slots: Vec<Mutex<Option<RunningTask>>>
+}
+
+impl Watchdog {
+
+    pub fn new(worker_count: usize, threshold: Option<Duration>, callback: Option<OverrunCallback>) -> Self {
+        Self {
+            threshold,
+            callback,
+            slots: (0..worker_count.max(1)).map(|_| Mutex::new(None)).collect()
+        }
+    }
+
+    /// 检查间隔：默认阈值的 1/10，限制在 1ms 到 100ms 之间
+    pub fn interval(&self) -> Duration {
+        let interval = self.threshold.map(|t| t / 10).unwrap_or(Duration::from_millis(100));
+        interval.clamp(Duration::from_millis(1), Duration::from_millis(100))
+    }
+
+    pub fn begin(&self, worker: usize, label: Option<String>, deadline: Option<Duration>) {
+        *self.slots[worker].lock().unwrap() = Some(RunningTask {
+            label,
+            started: Instant::now(),
+            limit: deadline.or(self.threshold),
+            reported: false
+        });
+    }
+
+    /// 任务结束。如果它超时了但 watchdog 还没来得及发现（比如模拟模式下根本没有 watchdog 线程），在这里补报
+    pub fn end(&self, worker: usize, metrics: &Metrics) {
+        let task = self.slots[worker].lock().unwrap().take();
+        if let Some(report) = task.and_then(|mut task| Self::check(worker, &mut task)) {
+            self.report(&report, metrics);
+        }
+    }
+
+    /// 检查所有工作线程，报告新发现的超时任务
+    pub fn scan(&self, metrics: &Metrics) {
+        for (worker, slot) in self.slots.iter().enumerate() {
+            // 回调在锁外执行，避免慢回调拖住工作线程
+            let report = slot.lock().unwrap().as_mut().and_then(|task| Self::check(worker, task));
+            if let Some(report) = report {
+                self.report(&report, metrics);
+            }
+        }
+    }
+
+    fn check(worker: usize, task: &mut RunningTask) -> Option<OverrunReport> {
+        let limit = task.limit?;
+        let elapsed = task.started.elapsed();
+        if task.reported || elapsed <= limit {
+            return None;
+        }
+        task.reported = true;
+        Some(OverrunReport {
+            worker,
+            label: task.label.clone(),
+            elapsed,
+            limit
+        })
+    }
+
+    fn report(&self, report: &OverrunReport, metrics: &Metrics) {
+        metrics.record_overrun();
+        match &self.callback {
+            Some(callback) => callback(report),
+            None => eprintln!("[watchdog] {}", report)
+        }
+    }
+
+}